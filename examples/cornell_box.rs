@@ -10,10 +10,18 @@ use stantz::{
     lighting::{Color, Light},
     materials::Material,
     objects::Object,
-    rendering::render,
+    rendering::{
+        aov::Aov,
+        denoising::ATrousDenoiser,
+        framebuffer::OutputSettings,
+        integrators::{
+            path_tracing::PathTracingIntegrator, whitted::WhittedIntegrator, Integrator,
+        },
+        render, RenderSettings,
+    },
 };
 
-const USAGE: &str = "cargo run --example cornell_box WIDTH HEIGHT ANTI_ALIASING FILENAME";
+const USAGE: &str = "cargo run --example cornell_box WIDTH HEIGHT ANTI_ALIASING FILENAME [PATHS]";
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -34,6 +42,29 @@ fn main() {
         .expect("ANTI_ALIASING must be an unsigned integer");
     let filename = &args[4];
 
+    // Path tracing is opt-in, since it needs many samples to converge
+    let paths = args.get(5).map(|paths| {
+        paths
+            .parse::<u32>()
+            .expect("PATHS must be an unsigned integer")
+    });
+    let integrator: Box<dyn Integrator> = match paths {
+        Some(samples) => Box::new(PathTracingIntegrator {
+            samples,
+            ..PathTracingIntegrator::default()
+        }),
+        None => Box::new(WhittedIntegrator::default()),
+    };
+
+    // Path traced renders are denoised, guided by these AOVs
+    let settings = RenderSettings {
+        aovs: match paths {
+            Some(_) => vec![Aov::Albedo, Aov::Normal, Aov::Depth],
+            None => vec![],
+        },
+        ..RenderSettings::default()
+    };
+
     let green_wall_x: f32 = 1.5;
     let red_wall_x: f32 = -1.5;
     let ceiling_y: f32 = 1.2;
@@ -46,11 +77,12 @@ fn main() {
         radius: 0.5,
     })];
     let sphere_1_material = Material {
-        ambient_color: Color::new(0.0, 0.0, 0.0),
-        diffuse_color: Color::new(0.3, 0.3, 0.3),
-        specular_color: Color::new(1.0, 1.0, 1.0),
+        ambient_color: Color::from_srgb(0.0, 0.0, 0.0),
+        diffuse_color: Color::from_srgb(0.3, 0.3, 0.3),
+        specular_color: Color::from_srgb(1.0, 1.0, 1.0),
         shininess: 50,
         reflectance: 0.75,
+        dielectric: None,
     };
 
     let sphere_2_mesh = vec![Geometry::Sphere(SphereGeometry {
@@ -58,11 +90,12 @@ fn main() {
         radius: 0.25,
     })];
     let sphere_2_material = Material {
-        ambient_color: Color::new(0.0, 0.0, 0.0),
-        diffuse_color: Color::new(0.75, 0.0, 0.0),
-        specular_color: Color::new(0.25, 0.0, 0.0),
+        ambient_color: Color::from_srgb(0.0, 0.0, 0.0),
+        diffuse_color: Color::from_srgb(0.75, 0.0, 0.0),
+        specular_color: Color::from_srgb(0.25, 0.0, 0.0),
         shininess: 10,
         reflectance: 0.2,
+        dielectric: None,
     };
 
     let floor_mesh = vec![
@@ -70,19 +103,24 @@ fn main() {
             a: Vector3::new(red_wall_x, ceiling_y, back_wall_z),
             b: Vector3::new(red_wall_x, floor_y, back_wall_z),
             c: Vector3::new(green_wall_x, ceiling_y, back_wall_z),
+            normals: None,
+            uvs: None,
         }),
         Geometry::Triangle(TriangleGeometry {
             a: Vector3::new(green_wall_x, floor_y, front_wall_z),
             b: Vector3::new(green_wall_x, floor_y, back_wall_z),
             c: Vector3::new(red_wall_x, floor_y, front_wall_z),
+            normals: None,
+            uvs: None,
         }),
     ];
     let floor_material = Material {
-        ambient_color: Color::new(0.0, 0.0, 0.0),
-        diffuse_color: Color::new(0.9, 0.8, 0.7),
-        specular_color: Color::new(0.0, 0.0, 0.0),
+        ambient_color: Color::from_srgb(0.0, 0.0, 0.0),
+        diffuse_color: Color::from_srgb(0.9, 0.8, 0.7),
+        specular_color: Color::from_srgb(0.0, 0.0, 0.0),
         shininess: 0,
         reflectance: 0.25,
+        dielectric: None,
     };
 
     let red_wall_mesh = vec![
@@ -90,19 +128,24 @@ fn main() {
             a: Vector3::new(red_wall_x, floor_y, front_wall_z),
             b: Vector3::new(red_wall_x, floor_y, back_wall_z),
             c: Vector3::new(red_wall_x, ceiling_y, front_wall_z),
+            normals: None,
+            uvs: None,
         }),
         Geometry::Triangle(TriangleGeometry {
             a: Vector3::new(red_wall_x, ceiling_y, back_wall_z),
             b: Vector3::new(red_wall_x, ceiling_y, front_wall_z),
             c: Vector3::new(red_wall_x, floor_y, back_wall_z),
+            normals: None,
+            uvs: None,
         }),
     ];
     let red_wall_material = Material {
-        ambient_color: Color::new(0.0, 0.0, 0.0),
-        diffuse_color: Color::new(1.0, 0.0, 0.0),
-        specular_color: Color::new(0.0, 0.0, 0.0),
+        ambient_color: Color::from_srgb(0.0, 0.0, 0.0),
+        diffuse_color: Color::from_srgb(1.0, 0.0, 0.0),
+        specular_color: Color::from_srgb(0.0, 0.0, 0.0),
         shininess: 0,
         reflectance: 0.5,
+        dielectric: None,
     };
 
     let green_wall_mesh = vec![
@@ -110,19 +153,24 @@ fn main() {
             a: Vector3::new(green_wall_x, floor_y, front_wall_z),
             b: Vector3::new(green_wall_x, ceiling_y, front_wall_z),
             c: Vector3::new(green_wall_x, floor_y, back_wall_z),
+            normals: None,
+            uvs: None,
         }),
         Geometry::Triangle(TriangleGeometry {
             a: Vector3::new(green_wall_x, ceiling_y, back_wall_z),
             b: Vector3::new(green_wall_x, floor_y, back_wall_z),
             c: Vector3::new(green_wall_x, ceiling_y, front_wall_z),
+            normals: None,
+            uvs: None,
         }),
     ];
     let green_wall_material = Material {
-        ambient_color: Color::new(0.0, 0.0, 0.0),
-        diffuse_color: Color::new(0.0, 1.0, 0.0),
-        specular_color: Color::new(0.0, 0.0, 0.0),
+        ambient_color: Color::from_srgb(0.0, 0.0, 0.0),
+        diffuse_color: Color::from_srgb(0.0, 1.0, 0.0),
+        specular_color: Color::from_srgb(0.0, 0.0, 0.0),
         shininess: 0,
         reflectance: 0.5,
+        dielectric: None,
     };
 
     let back_wall_mesh = vec![
@@ -130,11 +178,15 @@ fn main() {
             a: Vector3::new(red_wall_x, floor_y, back_wall_z),
             b: Vector3::new(red_wall_x, floor_y, front_wall_z),
             c: Vector3::new(green_wall_x, floor_y, back_wall_z),
+            normals: None,
+            uvs: None,
         }),
         Geometry::Triangle(TriangleGeometry {
             a: Vector3::new(green_wall_x, floor_y, back_wall_z),
             b: Vector3::new(green_wall_x, ceiling_y, back_wall_z),
             c: Vector3::new(red_wall_x, floor_y, back_wall_z),
+            normals: None,
+            uvs: None,
         }),
     ];
 
@@ -143,11 +195,15 @@ fn main() {
             a: Vector3::new(green_wall_x, ceiling_y, front_wall_z),
             b: Vector3::new(red_wall_x, ceiling_y, back_wall_z),
             c: Vector3::new(green_wall_x, ceiling_y, back_wall_z),
+            normals: None,
+            uvs: None,
         }),
         Geometry::Triangle(TriangleGeometry {
             a: Vector3::new(red_wall_x, ceiling_y, back_wall_z),
             b: Vector3::new(green_wall_x, ceiling_y, front_wall_z),
             c: Vector3::new(red_wall_x, ceiling_y, front_wall_z),
+            normals: None,
+            uvs: None,
         }),
     ];
 
@@ -156,19 +212,24 @@ fn main() {
             a: Vector3::new(-0.5, 1.0, -3.7),
             b: Vector3::new(0.5, 1.0, -3.7),
             c: Vector3::new(-0.5, 1.0, -2.7),
+            normals: None,
+            uvs: None,
         }),
         Geometry::Triangle(TriangleGeometry {
             a: Vector3::new(0.5, 1.0, -2.7),
             b: Vector3::new(0.5, 1.0, -3.7),
             c: Vector3::new(-0.5, 1.0, -2.7),
+            normals: None,
+            uvs: None,
         }),
     ];
     let light_fixture_material = Material {
-        ambient_color: Color::new(0.0, 0.0, 0.0),
-        diffuse_color: Color::new(0.0, 0.0, 0.0),
-        specular_color: Color::new(1.0, 1.0, 1.0),
+        ambient_color: Color::from_srgb(0.0, 0.0, 0.0),
+        diffuse_color: Color::from_srgb(0.0, 0.0, 0.0),
+        specular_color: Color::from_srgb(1.0, 1.0, 1.0),
         shininess: 0,
         reflectance: 0.0,
+        dielectric: None,
     };
 
     let objects: Vec<Object> = vec![
@@ -214,17 +275,32 @@ fn main() {
     let camera = Camera {
         fov: 45.0,
         focal_length: 15.0,
+        ..Camera::default()
     };
 
     let now = Instant::now();
-    render(
+    let mut framebuffer = render(
         &objects,
         &lights,
         &camera,
         image_width,
         image_height,
         anti_aliasing,
-        filename,
-    );
+        integrator.as_ref(),
+        &settings,
+    )
+    .unwrap_or_else(|error| {
+        println!("{}", error);
+        process::exit(1);
+    });
     println!("Cornell Box rendered in {:.2?}", now.elapsed());
+
+    if paths.is_some() {
+        ATrousDenoiser::default().denoise(&mut framebuffer);
+    }
+
+    if let Err(error) = framebuffer.save(filename, &OutputSettings::default()) {
+        println!("Failed to write {}: {}", filename, error);
+        process::exit(1);
+    }
 }
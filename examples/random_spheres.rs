@@ -13,7 +13,10 @@ use stantz::{
     lighting::{Color, Light},
     materials::Material,
     objects::Object,
-    rendering::render,
+    rendering::{
+        framebuffer::OutputSettings, integrators::whitted::WhittedIntegrator, render,
+        RenderSettings,
+    },
 };
 
 const USAGE: &str =
@@ -64,36 +67,34 @@ fn main() {
 
     let materials: Vec<Material> = (0..num_spheres)
         .map(|_| Material {
-            ambient_color: Color::new(
+            ambient_color: Color::from_srgb(
                 lerp(0.0, 0.1, rng.gen::<f32>()),
                 lerp(0.0, 0.1, rng.gen::<f32>()),
                 lerp(0.0, 0.1, rng.gen::<f32>()),
             ),
-            diffuse_color: Color::new(
+            diffuse_color: Color::from_srgb(
                 lerp(0.0, 1.0, rng.gen::<f32>()),
                 lerp(0.0, 1.0, rng.gen::<f32>()),
                 lerp(0.0, 1.0, rng.gen::<f32>()),
             ),
-            specular_color: Color::new(
+            specular_color: Color::from_srgb(
                 lerp(0.0, 1.0, rng.gen::<f32>()),
                 lerp(0.0, 1.0, rng.gen::<f32>()),
                 lerp(0.0, 1.0, rng.gen::<f32>()),
             ),
             shininess: lerp(0.0, 100.0, rng.gen::<f32>()) as i32,
             reflectance: lerp(0.0, 1.0, rng.gen::<f32>()),
+            dielectric: None,
         })
         .collect();
 
     let objects: Vec<Object> = spheres_meshes
         .iter()
         .zip(materials.iter())
-        .map(|(mesh, material)| Object {
-            mesh: mesh,
-            material: material,
-        })
+        .map(|(mesh, material)| Object { mesh, material })
         .collect();
 
-    let lights = (0..num_lights)
+    let lights: Vec<Light> = (0..num_lights)
         .map(|_| Light {
             position: Vector3::new(
                 lerp(-4.0, 4.0, rng.gen::<f32>()),
@@ -111,19 +112,30 @@ fn main() {
     let camera = Camera {
         fov: 45.0,
         focal_length: 15.0,
+        ..Camera::default()
     };
 
     let now = Instant::now();
-    render(
+    let framebuffer = render(
         &objects,
         &lights,
         &camera,
         image_width,
         image_height,
         anti_aliasing,
-        filename,
-    );
+        &WhittedIntegrator::default(),
+        &RenderSettings::default(),
+    )
+    .unwrap_or_else(|error| {
+        println!("{}", error);
+        process::exit(1);
+    });
     println!("Random spheres rendered in {:.2?}", now.elapsed());
+
+    if let Err(error) = framebuffer.save(filename, &OutputSettings::default()) {
+        println!("Failed to write {}: {}", filename, error);
+        process::exit(1);
+    }
 }
 
 fn lerp(a: f32, b: f32, t: f32) -> f32 {
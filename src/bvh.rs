@@ -1,5 +1,11 @@
-use crate::geometry::Geometry;
-use crate::materials::Material;
+use na::Vector3;
+
+use crate::geometry::{aa_box::AABoxGeometry, Center, Hit, Intersection, AABB};
+use crate::objects::Primitive;
+use crate::rendering::ray::Ray;
+
+// Relative cost of testing a ray against a node's AABB versus a primitive
+const TRAVERSAL_COST: f32 = 0.125;
 
 #[derive(Debug)]
 pub struct BVH<'a> {
@@ -9,7 +15,307 @@ pub struct BVH<'a> {
 #[derive(Debug)]
 // Ideally, object should only be Some when both children are None
 pub struct BVHNode<'a> {
-    pub object: Option<&'a (Geometry, Material)>,
+    pub aabb: AABoxGeometry,
+    pub object: Option<Primitive<'a>>,
     pub left_child: Option<Box<Self>>,
     pub right_child: Option<Box<Self>>,
 }
+
+impl<'a> BVH<'a> {
+    pub fn new(primitives: &[Primitive<'a>]) -> Self {
+        let mut entries: Vec<BuildEntry> = primitives
+            .iter()
+            .map(|&primitive| BuildEntry {
+                aabb: primitive.geometry.aabb(),
+                center: primitive.geometry.center(),
+                primitive,
+            })
+            .collect();
+
+        Self {
+            root: BVHNode::build(&mut entries),
+        }
+    }
+
+    /// Finds the nearest primitive hit with `t` in `[t_min, t_max]`
+    pub fn intersection(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(Hit, Primitive<'a>)> {
+        self.root.intersection(ray, t_min, t_max)
+    }
+
+    /// Whether anything is hit with `t` in `[t_min, t_max]`, which can stop at
+    /// the first hit found rather than the nearest
+    pub fn is_occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.root.is_occluded(ray, t_min, t_max)
+    }
+}
+
+struct BuildEntry<'a> {
+    aabb: AABoxGeometry,
+    center: Vector3<f32>,
+    primitive: Primitive<'a>,
+}
+
+impl<'a> BVHNode<'a> {
+    fn build(entries: &mut [BuildEntry<'a>]) -> Self {
+        let aabb = entries
+            .iter()
+            .map(|e| e.aabb.clone())
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or_else(AABoxGeometry::empty);
+
+        match entries {
+            [] => Self {
+                aabb,
+                object: None,
+                left_child: None,
+                right_child: None,
+            },
+            [only] => Self {
+                aabb,
+                object: Some(only.primitive),
+                left_child: None,
+                right_child: None,
+            },
+            _ => {
+                let (axis, split) = sah_split(entries, &aabb);
+
+                entries.sort_by(|p, q| p.center[axis].total_cmp(&q.center[axis]));
+                let (left, right) = entries.split_at_mut(split);
+
+                Self {
+                    aabb,
+                    object: None,
+                    left_child: Some(Box::new(Self::build(left))),
+                    right_child: Some(Box::new(Self::build(right))),
+                }
+            }
+        }
+    }
+
+    fn intersection<'b>(
+        &'b self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(Hit, Primitive<'a>)> {
+        if let Some(primitive) = self.object {
+            return primitive
+                .geometry
+                .intersection(ray, t_min, t_max)
+                .map(|hit| (hit, primitive));
+        }
+
+        // Visit the nearer child first, so the farther one can often be culled
+        let entry = |child: &'b Option<Box<Self>>| {
+            let child = child.as_deref()?;
+            entry_distance(&child.aabb, ray, t_min, t_max).map(|t| (t, child))
+        };
+        let mut children = [entry(&self.left_child), entry(&self.right_child)];
+        if let [Some((t_left, _)), Some((t_right, _))] = children {
+            if t_right < t_left {
+                children.swap(0, 1);
+            }
+        }
+
+        let mut nearest = None;
+        let mut t_max = t_max;
+
+        for (t_entry, child) in children.into_iter().flatten() {
+            if t_entry > t_max {
+                break;
+            }
+            if let Some(hit) = child.intersection(ray, t_min, t_max) {
+                t_max = hit.0.t;
+                nearest = Some(hit);
+            }
+        }
+
+        nearest
+    }
+}
+
+impl BVHNode<'_> {
+    fn is_occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        if let Some(primitive) = self.object {
+            return primitive.geometry.intersection(ray, t_min, t_max).is_some();
+        }
+
+        [&self.left_child, &self.right_child]
+            .into_iter()
+            .flatten()
+            .any(|child| {
+                entry_distance(&child.aabb, ray, t_min, t_max).is_some()
+                    && child.is_occluded(ray, t_min, t_max)
+            })
+    }
+}
+
+/// Chooses the axis and sorted index that minimize the surface area heuristic,
+/// or the middle of the axis the centers spread furthest along if no split is
+/// cheaper than the others
+fn sah_split(entries: &mut [BuildEntry], aabb: &AABoxGeometry) -> (usize, usize) {
+    let parent_area = aabb.surface_area();
+    let (center_min, center_max) = entries.iter().fold(
+        (
+            Vector3::repeat(f32::INFINITY),
+            Vector3::repeat(f32::NEG_INFINITY),
+        ),
+        |(min, max), e| (min.inf(&e.center), max.sup(&e.center)),
+    );
+    let center_extent = center_max - center_min;
+
+    let median = (center_extent.imax(), entries.len() / 2);
+    let mut best = (median.0, median.1, f32::INFINITY);
+    let mut worst_cost = f32::NEG_INFINITY;
+
+    for axis in 0..3 {
+        // Entries can't be told apart along an axis where their centers all
+        // coincide
+        if center_extent[axis] <= 0.0 {
+            continue;
+        }
+
+        entries.sort_by(|p, q| p.center[axis].total_cmp(&q.center[axis]));
+
+        // right_areas[i] is the area of the box around entries[i..]
+        let mut right_areas = vec![0.0; entries.len()];
+        let mut right_aabb = AABoxGeometry::empty();
+        for (i, e) in entries.iter().enumerate().rev() {
+            right_aabb = right_aabb.union(&e.aabb);
+            right_areas[i] = right_aabb.surface_area();
+        }
+
+        let mut left_aabb = AABoxGeometry::empty();
+        for split in 1..entries.len() {
+            left_aabb = left_aabb.union(&entries[split - 1].aabb);
+
+            let num_left = split as f32;
+            let num_right = (entries.len() - split) as f32;
+            let cost = TRAVERSAL_COST
+                + (left_aabb.surface_area() * num_left + right_areas[split] * num_right)
+                    / parent_area;
+
+            if cost < best.2 {
+                best = (axis, split, cost);
+            }
+            worst_cost = worst_cost.max(cost);
+        }
+    }
+
+    // Splits that all cost the same, e.g., of primitives with identical boxes,
+    // would otherwise peel off one primitive per level, and the recursive
+    // traversal could overflow the stack on the resulting chain
+    if best.2 >= worst_cost {
+        return median;
+    }
+
+    (best.0, best.1)
+}
+
+/// Distance at which the ray enters the box, clamped to `[t_min, t_max]`
+fn entry_distance(aabb: &AABoxGeometry, ray: &Ray, t_min: f32, t_max: f32) -> Option<f32> {
+    let inverse_direction = ray.direction().map(|d| 1.0 / d);
+
+    let (t_entry, t_exit) = (0..3).fold((t_min, t_max), |(t_entry, t_exit), axis| {
+        let t_0 = (aabb.min[axis] - ray.origin[axis]) * inverse_direction[axis];
+        let t_1 = (aabb.max[axis] - ray.origin[axis]) * inverse_direction[axis];
+
+        (t_entry.max(t_0.min(t_1)), t_exit.min(t_0.max(t_1)))
+    });
+
+    (t_entry <= t_exit).then_some(t_entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        geometry::{sphere::SphereGeometry, Geometry},
+        lighting::Color,
+        materials::Material,
+    };
+
+    fn material() -> Material {
+        Material {
+            ambient_color: Color::new_black(),
+            diffuse_color: Color::new(0.5, 0.5, 0.5),
+            specular_color: Color::new_black(),
+            shininess: 1,
+            reflectance: 0.0,
+            dielectric: None,
+        }
+    }
+
+    fn spheres(centers: &[Vector3<f32>]) -> Vec<Geometry> {
+        centers
+            .iter()
+            .map(|&center| {
+                Geometry::Sphere(SphereGeometry {
+                    center,
+                    radius: 0.5,
+                })
+            })
+            .collect()
+    }
+
+    fn primitives<'a>(geometries: &'a [Geometry], material: &'a Material) -> Vec<Primitive<'a>> {
+        geometries
+            .iter()
+            .enumerate()
+            .map(|(object_id, geometry)| Primitive {
+                geometry,
+                material,
+                object_id,
+                material_id: 0,
+            })
+            .collect()
+    }
+
+    fn depth(node: &BVHNode) -> usize {
+        let children = [&node.left_child, &node.right_child];
+        1 + children
+            .into_iter()
+            .flatten()
+            .map(|child| depth(child))
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn balances_coincident_primitives() {
+        let geometries = spheres(&vec![Vector3::new(0.0, 0.0, -5.0); 3000]);
+        let material = material();
+        let bvh = BVH::new(&primitives(&geometries, &material));
+
+        // A balanced tree of 3000 leaves is 13 levels deep
+        assert!(depth(&bvh.root) <= 13, "depth is {}", depth(&bvh.root));
+
+        let ray = Ray::new(Vector3::zeros(), -Vector3::z());
+        assert!(bvh.intersection(&ray, 0.0, f32::INFINITY).is_some());
+        assert!(bvh.is_occluded(&ray, 0.0, f32::INFINITY));
+    }
+
+    #[test]
+    fn finds_the_nearest_hit() {
+        let centers: Vec<Vector3<f32>> = (1..=20)
+            .map(|k| Vector3::new(0.0, 0.0, -2.0 * k as f32))
+            .collect();
+        let geometries = spheres(&centers);
+        let material = material();
+        let bvh = BVH::new(&primitives(&geometries, &material));
+
+        for (origin, direction, nearest) in [
+            (Vector3::zeros(), -Vector3::z(), 0),
+            (Vector3::new(0.0, 0.0, -50.0), Vector3::z(), 19),
+        ] {
+            let ray = Ray::new(origin, direction);
+            let (_, primitive) = bvh.intersection(&ray, 0.0, f32::INFINITY).unwrap();
+
+            assert_eq!(primitive.object_id, nearest);
+        }
+
+        let ray = Ray::new(Vector3::zeros(), Vector3::x());
+        assert!(bvh.intersection(&ray, 0.0, f32::INFINITY).is_none());
+        assert!(!bvh.is_occluded(&ray, 0.0, f32::INFINITY));
+    }
+}
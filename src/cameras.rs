@@ -1,6 +1,46 @@
+pub mod stereo;
+
+use std::f32::consts::PI;
+
+use na::{UnitQuaternion, Vector2, Vector3};
+use rand::Rng;
+use rand_pcg::Pcg32;
+
+use crate::rendering::ray::Ray;
+
+/// Looks down its local negative z axis, with y up. By default, it sits at the
+/// origin, so local and world space are the same.
+#[derive(Debug, Clone)]
 pub struct Camera {
+    pub projection: Projection,
+    /// Vertical field of view in degrees for perspective projections, or the
+    /// field of view across the image circle for fisheye ones
     pub fov: f32,
     pub focal_length: f32,
+    pub position: Vector3<f32>,
+    /// Rotation from camera space into world space
+    pub orientation: UnitQuaternion<f32>,
+    /// Lens opening for depth of field, or None for a pinhole camera, which
+    /// keeps everything in focus
+    pub aperture: Option<Aperture>,
+    /// Distance of the eye to the right of `position`, or to the left when
+    /// negative, for stereo rigs. Equirectangular cameras move the eye around
+    /// a circle instead, so every direction sees in stereo.
+    pub eye_offset: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            projection: Projection::Perspective,
+            fov: 45.0,
+            focal_length: 1.0,
+            position: Vector3::zeros(),
+            orientation: UnitQuaternion::identity(),
+            aperture: None,
+            eye_offset: 0.0,
+        }
+    }
 }
 
 impl Camera {
@@ -9,4 +49,207 @@ impl Camera {
 
         (fov_radians / 2.0).tan() * 2.0 * self.focal_length
     }
+
+    /// Moves the camera to `position` and turns it to face `target`, rolled so
+    /// that `up` points as close to the top of the image as it can. `up` must
+    /// not be parallel to the direction of `target`.
+    pub fn look_at(&mut self, position: Vector3<f32>, target: Vector3<f32>, up: Vector3<f32>) {
+        self.position = position;
+        // face_towards turns the z axis towards its target, and the camera
+        // looks down the negative z axis
+        self.orientation = UnitQuaternion::face_towards(&(position - target), &up);
+    }
+
+    /// Height of the image in camera space, on the focal plane for perspective
+    /// projections. Panoramic projections work in units of the image height.
+    pub fn view_height(&self) -> f32 {
+        match self.projection {
+            Projection::Perspective => self.focal_plane_height(),
+            Projection::Orthographic { view_height } => view_height,
+            Projection::Equirectangular | Projection::Fisheye { .. } | Projection::Cubemap => 1.0,
+        }
+    }
+
+    /// World-space ray for `point` on the image, given in camera space and
+    /// scaled to `view_height`, or None if the projection doesn't cover it.
+    /// With an aperture, the ray starts from a random point on the lens
+    /// instead, and passes through where the pinhole ray crosses the plane in
+    /// focus.
+    pub fn ray(&self, point: &Vector2<f32>, rng: &mut Pcg32) -> Option<Ray> {
+        let (origin, direction) = match &self.projection {
+            Projection::Perspective => (Vector3::zeros(), point.push(-self.focal_length)),
+            Projection::Orthographic { .. } => (point.push(0.0), -Vector3::z()),
+            panoramic => (Vector3::zeros(), panoramic.direction(point, self.fov)?),
+        };
+        let origin = origin + self.eye(point);
+
+        // Panoramic rays can point sideways or backwards, so they never cross
+        // a plane in front of the lens
+        let aperture = match (&self.aperture, &self.projection) {
+            (Some(aperture), Projection::Perspective | Projection::Orthographic { .. }) => aperture,
+            _ => {
+                return Some(Ray::new(
+                    self.position + self.orientation * origin,
+                    self.orientation * direction,
+                ))
+            }
+        };
+
+        let lens_point = origin + aperture.sample(rng).push(0.0);
+        let focus_point = origin + direction * (aperture.focus_distance / -direction.z);
+
+        Some(Ray::new(
+            self.position + self.orientation * lens_point,
+            self.orientation * (focus_point - lens_point),
+        ))
+    }
+
+    /// Camera-space offset of the eye that the ray for `point` starts from.
+    /// For equirectangular cameras, it's at right angles to the longitude of
+    /// the ray, i.e., omnidirectional stereo.
+    fn eye(&self, point: &Vector2<f32>) -> Vector3<f32> {
+        match self.projection {
+            Projection::Equirectangular => {
+                let longitude = point.x * PI;
+                Vector3::new(longitude.cos(), 0.0, longitude.sin()) * self.eye_offset
+            }
+            _ => Vector3::x() * self.eye_offset,
+        }
+    }
+}
+
+/// How points on the image map to rays leaving the camera
+#[derive(Debug, Clone)]
+pub enum Projection {
+    /// Rays spread out from the camera position, covering `Camera::fov`
+    /// vertically, so farther objects look smaller
+    Perspective,
+    /// Rays run parallel from the plane through the camera position, so
+    /// objects keep their size however far away they are
+    Orthographic {
+        /// Height of the view in world units
+        view_height: f32,
+    },
+    /// Longitude across and latitude down the image, with the view direction
+    /// in the middle. A 2:1 image covers the whole sphere.
+    Equirectangular,
+    /// A circle as tall as the image, with the view direction in the middle
+    /// and `Camera::fov` across it. Nothing is rendered outside the circle.
+    Fisheye { mapping: FisheyeMapping },
+    /// Six 90° faces side by side, in the order +x, -x, +y, -y, +z, -z of
+    /// camera space, with the usual cubemap orientations. A 6:1 image gives
+    /// square faces.
+    Cubemap,
+}
+
+/// How the angle from the view direction maps to the distance from the
+/// center of a fisheye image
+#[derive(Debug, Clone, Copy)]
+pub enum FisheyeMapping {
+    /// Distance grows linearly with the angle
+    Angular,
+    /// Equal areas of the image cover equal solid angles
+    Equisolid,
+}
+
+impl Projection {
+    /// Camera-space direction for `point` of a panoramic projection, given in
+    /// units of the image height
+    fn direction(&self, point: &Vector2<f32>, fov: f32) -> Option<Vector3<f32>> {
+        match self {
+            Projection::Perspective | Projection::Orthographic { .. } => None,
+            Projection::Equirectangular => {
+                let [longitude, latitude] = [point.x * PI, point.y * PI];
+
+                Some(Vector3::new(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                ))
+            }
+            Projection::Fisheye { mapping } => {
+                let radius = point.norm();
+                if radius > 0.5 {
+                    return None;
+                }
+
+                let fov_radians = fov.to_radians();
+                let angle = match mapping {
+                    FisheyeMapping::Angular => radius * fov_radians,
+                    FisheyeMapping::Equisolid => {
+                        2.0 * (2.0 * radius * (fov_radians / 4.0).sin()).asin()
+                    }
+                };
+                let azimuth = point.y.atan2(point.x);
+
+                Some(Vector3::new(
+                    angle.sin() * azimuth.cos(),
+                    angle.sin() * azimuth.sin(),
+                    -angle.cos(),
+                ))
+            }
+            Projection::Cubemap => {
+                // Points run from -3 to 3 across the strip, so each face is 1
+                // wide, and the last column of samples stays on the last face
+                let face = (point.x + 3.0).floor().clamp(0.0, 5.0);
+                let [u, v] = [(point.x + 3.0 - face - 0.5) * 2.0, point.y * 2.0];
+
+                let (forward, right, up) = match face as u32 {
+                    0 => (Vector3::x(), -Vector3::z(), Vector3::y()),
+                    1 => (-Vector3::x(), Vector3::z(), Vector3::y()),
+                    2 => (Vector3::y(), Vector3::x(), -Vector3::z()),
+                    3 => (-Vector3::y(), Vector3::x(), Vector3::z()),
+                    4 => (Vector3::z(), Vector3::x(), Vector3::y()),
+                    _ => (-Vector3::z(), -Vector3::x(), Vector3::y()),
+                };
+
+                Some(forward + right * u + up * v)
+            }
+        }
+    }
+}
+
+/// A thin lens, which blurs objects more the farther they are from the focus
+/// distance. Out of focus highlights take on the shape of the opening.
+#[derive(Debug, Clone)]
+pub struct Aperture {
+    /// Radius of the opening, or of the circle through the corners of the
+    /// blades, in world units
+    pub radius: f32,
+    /// Distance along the view direction of the plane that's in focus
+    pub focus_distance: f32,
+    /// Number of straight blades forming a polygonal opening, or None for a
+    /// round one
+    pub blades: Option<u32>,
+    /// Rotation of the blades in degrees, counterclockwise from a corner
+    /// pointing right
+    pub rotation: f32,
+}
+
+impl Aperture {
+    /// Uniformly random point on the opening, in camera space
+    fn sample(&self, rng: &mut Pcg32) -> Vector2<f32> {
+        let point = match self.blades {
+            // Fewer than 3 blades can't close around an opening
+            Some(blades) if blades >= 3 => {
+                // The polygon is split into equal triangles around the center,
+                // so one is picked uniformly, then a point within it
+                let blade = rng.gen_range(0..blades) as f32;
+                let corner_angle =
+                    |k: f32| 2.0 * PI * k / blades as f32 + self.rotation.to_radians();
+                let corner = |k: f32| Vector2::new(corner_angle(k).cos(), corner_angle(k).sin());
+
+                let [u, v]: [f32; 2] = [rng.gen(), rng.gen()];
+                let u = u.sqrt();
+
+                corner(blade) * (u * (1.0 - v)) + corner(blade + 1.0) * (u * v)
+            }
+            _ => {
+                let angle = 2.0 * PI * rng.gen::<f32>();
+                Vector2::new(angle.cos(), angle.sin()) * rng.gen::<f32>().sqrt()
+            }
+        };
+
+        point * self.radius
+    }
 }
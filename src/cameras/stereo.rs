@@ -0,0 +1,72 @@
+use na::{UnitQuaternion, Vector3};
+
+use super::{Camera, Projection};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+/// How the eyes are turned relative to each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoMode {
+    /// Both eyes look the same way, so their views meet at infinity
+    Parallel,
+    /// Each eye turns inwards, so their views meet at the convergence distance
+    ToeIn,
+}
+
+/// A pair of cameras side by side, centered on `camera`, which sets the
+/// projection and placement of both eyes
+#[derive(Debug, Clone)]
+pub struct StereoCamera {
+    pub camera: Camera,
+    /// Distance between the eyes in world units
+    pub interocular_distance: f32,
+    /// Distance along the view direction where the eyes converge, for toe-in
+    /// rigs
+    pub convergence_distance: f32,
+    /// Equirectangular cameras ignore the mode, since their eyes turn with
+    /// every ray instead
+    pub mode: StereoMode,
+}
+
+impl Default for StereoCamera {
+    fn default() -> Self {
+        Self {
+            camera: Camera::default(),
+            interocular_distance: 0.065,
+            convergence_distance: 10.0,
+            mode: StereoMode::Parallel,
+        }
+    }
+}
+
+impl StereoCamera {
+    /// Camera for one eye, which generates primary rays like any other
+    pub fn eye(&self, eye: Eye) -> Camera {
+        let eye_offset = match eye {
+            Eye::Left => -self.interocular_distance / 2.0,
+            Eye::Right => self.interocular_distance / 2.0,
+        };
+
+        let mut camera = self.camera.clone();
+
+        if let Projection::Equirectangular = camera.projection {
+            camera.eye_offset += eye_offset;
+            return camera;
+        }
+
+        camera.position += camera.orientation * Vector3::x() * eye_offset;
+        if self.mode == StereoMode::ToeIn {
+            // Turning about the y axis towards the other eye, so that the view
+            // direction crosses the middle of the rig at the convergence
+            // distance
+            let angle = (eye_offset / self.convergence_distance).atan();
+            camera.orientation *= UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle);
+        }
+
+        camera
+    }
+}
@@ -2,7 +2,7 @@ pub mod aa_box;
 pub mod sphere;
 pub mod triangle;
 
-use na::Vector3;
+use na::{Vector2, Vector3};
 
 use crate::rendering::ray::Ray;
 
@@ -21,15 +21,61 @@ pub trait Center {
     fn center(&self) -> Vector3<f32>;
 }
 
-pub trait NormalAt {
-    fn normal_at(&self, point: &Vector3<f32>) -> Vector3<f32>;
+/// Everything known about where a ray hit a surface. Both normals face the
+/// side the ray came from, and `front_face` records whether that's the outside.
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub t: f32,
+    pub point: Vector3<f32>,
+    /// Normal of the actual surface
+    pub geometric_normal: Vector3<f32>,
+    /// Normal used for lighting, e.g., interpolated from vertex normals
+    pub shading_normal: Vector3<f32>,
+    pub uv: Vector2<f32>,
+    /// Weights of a triangle's vertices, for triangle hits only
+    pub barycentrics: Option<Vector3<f32>>,
+    pub front_face: bool,
+}
+
+impl Hit {
+    /// Builds a hit from outward facing normals, flipping them toward the ray
+    pub fn new(
+        ray: &Ray,
+        t: f32,
+        outward_normal: Vector3<f32>,
+        shading_normal: Vector3<f32>,
+        uv: Vector2<f32>,
+        barycentrics: Option<Vector3<f32>>,
+    ) -> Self {
+        let front_face = ray.direction().dot(&outward_normal) < 0.0;
+        let sign = if front_face { 1.0 } else { -1.0 };
+
+        Self {
+            t,
+            point: ray.point_at(t),
+            geometric_normal: outward_normal * sign,
+            shading_normal: shading_normal * sign,
+            uv,
+            barycentrics,
+            front_face,
+        }
+    }
+
+    /// Start of a ray leaving the surface, moved `bias` along the geometric
+    /// normal, so that floating point error can't make the ray hit the surface
+    /// it starts on. Since the normal faces the side the ray came from, a
+    /// negative bias is used for rays passing through to the other side.
+    pub fn offset_point(&self, bias: f32) -> Vector3<f32> {
+        self.point + self.geometric_normal * bias
+    }
 }
 
 pub trait Intersection<T, U> {
     type Argument;
     type Output;
 
-    fn intersection(&self, other: &Self::Argument) -> Option<Self::Output>;
+    /// Finds the nearest intersection with `t` in `[t_min, t_max]`
+    fn intersection(&self, other: &Self::Argument, t_min: f32, t_max: f32) -> Option<Self::Output>;
 }
 
 pub trait AABB {
@@ -46,25 +92,25 @@ impl Center for Geometry {
     }
 }
 
-impl Intersection<Ray, f32> for Geometry {
-    type Argument = Ray;
-    type Output = f32;
-
-    fn intersection(&self, other: &Self::Argument) -> Option<Self::Output> {
+impl AABB for Geometry {
+    fn aabb(&self) -> AABoxGeometry {
         match self {
-            Geometry::AABox(aa_box) => aa_box.intersection(other),
-            Geometry::Sphere(sphere) => sphere.intersection(other),
-            Geometry::Triangle(triangle) => triangle.intersection(other),
+            Geometry::AABox(aa_box) => aa_box.aabb(),
+            Geometry::Sphere(sphere) => sphere.aabb(),
+            Geometry::Triangle(triangle) => triangle.aabb(),
         }
     }
 }
 
-impl NormalAt for Geometry {
-    fn normal_at(&self, point: &Vector3<f32>) -> Vector3<f32> {
+impl Intersection<Ray, Hit> for Geometry {
+    type Argument = Ray;
+    type Output = Hit;
+
+    fn intersection(&self, other: &Self::Argument, t_min: f32, t_max: f32) -> Option<Self::Output> {
         match self {
-            Geometry::AABox(aa_box) => aa_box.normal_at(point),
-            Geometry::Sphere(sphere) => sphere.normal_at(point),
-            Geometry::Triangle(triangle) => triangle.normal_at(point),
+            Geometry::AABox(aa_box) => aa_box.intersection(other, t_min, t_max),
+            Geometry::Sphere(sphere) => sphere.intersection(other, t_min, t_max),
+            Geometry::Triangle(triangle) => triangle.intersection(other, t_min, t_max),
         }
     }
 }
@@ -1,8 +1,8 @@
-use na::Vector3;
+use na::{Vector2, Vector3};
 
 use crate::rendering::ray::Ray;
 
-use super::{Center, Intersection, NormalAt, AABB};
+use super::{Center, Hit, Intersection, AABB};
 
 #[derive(Debug, Clone)]
 pub struct AABoxGeometry {
@@ -10,6 +10,40 @@ pub struct AABoxGeometry {
     pub max: Vector3<f32>,
 }
 
+impl AABoxGeometry {
+    /// A box containing nothing, which is the identity for `union`
+    pub fn empty() -> Self {
+        Self {
+            min: Vector3::repeat(f32::INFINITY),
+            max: Vector3::repeat(f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    /// Position on the face perpendicular to `axis`, scaled to [0, 1]
+    fn uv(&self, point: &Vector3<f32>, axis: usize) -> Vector2<f32> {
+        let [u_axis, v_axis] = [(axis + 1) % 3, (axis + 2) % 3];
+        let size = self.max - self.min;
+
+        Vector2::new(
+            (point[u_axis] - self.min[u_axis]) / size[u_axis],
+            (point[v_axis] - self.min[v_axis]) / size[v_axis],
+        )
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = (self.max - self.min).map(|d| d.max(0.0));
+
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+}
+
 impl Center for AABoxGeometry {
     fn center(&self) -> Vector3<f32> {
         Vector3::new(
@@ -20,83 +54,63 @@ impl Center for AABoxGeometry {
     }
 }
 
-impl NormalAt for AABoxGeometry {
-    fn normal_at(&self, point: &Vector3<f32>) -> Vector3<f32> {
-        // TODO: Implement edge/corner cases (literally)
-        let is_left_of = point.x <= self.min.x;
-        let is_right_of = point.x >= self.max.x;
-        let is_below = point.y <= self.min.y;
-        let is_above = point.y >= self.max.y;
-        let is_behind = point.z <= self.min.z;
-        let is_in_front_of = point.z >= self.max.z;
-
-        let is_in_x_bounds = (point.x >= self.min.x && point.x <= self.min.x + 0.1)
-            || (point.x <= self.max.x && point.x >= self.max.x - 0.1);
-        let is_in_y_bounds = (point.y >= self.min.y && point.y <= self.min.y + 0.1)
-            || (point.y <= self.max.y && point.y >= self.max.y - 0.1);
-        let is_in_z_bounds = (point.z >= self.min.z && point.z <= self.min.z + 0.1)
-            || (point.z <= self.max.z && point.z >= self.max.z - 0.1);
-
-        if is_in_x_bounds || is_in_y_bounds {
-            if is_behind {
-                return Vector3::new(0.0, 0.0, -1.0);
-            }
-            if is_in_front_of {
-                return Vector3::new(0.0, 0.0, 1.0);
-            }
-        }
-        if is_in_x_bounds || is_in_z_bounds {
-            if is_below {
-                return Vector3::new(0.0, -1.0, 0.0);
-            }
-            if is_above {
-                return Vector3::new(0.0, 1.0, 0.0);
-            }
-        }
-        if is_in_y_bounds || is_in_z_bounds {
-            if is_left_of {
-                return Vector3::new(-1.0, 0.0, 0.0);
-            }
-            if is_right_of {
-                return Vector3::new(1.0, 0.0, 0.0);
-            }
-        }
-
-        Vector3::new(0.0, 0.0, 0.0)
-    }
-}
-
-impl Intersection<Ray, f32> for AABoxGeometry {
+impl Intersection<Ray, Hit> for AABoxGeometry {
     type Argument = Ray;
-    type Output = f32;
-
-    fn intersection(&self, other: &Self::Argument) -> Option<Self::Output> {
-        let left_t = (self.min.x - other.origin.x) / other.direction().x;
-        let right_t = (self.max.x - other.origin.x) / other.direction().x;
-        let bottom_t = (self.min.y - other.origin.y) / other.direction().y;
-        let top_t = (self.max.y - other.origin.y) / other.direction().y;
-        let back_t = (self.min.z - other.origin.z) / other.direction().z;
-        let front_t = (self.max.z - other.origin.z) / other.direction().z;
-
-        let t_min = left_t
-            .min(right_t)
-            .max(bottom_t.min(top_t))
-            .max(back_t.min(front_t));
-
-        let t_max = left_t
-            .max(right_t)
-            .min(bottom_t.max(top_t))
-            .min(back_t.max(front_t));
-
-        if t_max < 0.0 || t_min > t_max {
+    type Output = Hit;
+
+    fn intersection(&self, other: &Self::Argument, t_min: f32, t_max: f32) -> Option<Self::Output> {
+        // For each axis, the distances to the near and far slab planes
+        let slabs: Vec<(f32, f32)> = (0..3)
+            .map(|axis| {
+                let min_t = (self.min[axis] - other.origin[axis]) / other.direction()[axis];
+                let max_t = (self.max[axis] - other.origin[axis]) / other.direction()[axis];
+
+                // A ray parallel to, and lying on, a slab plane is inside the slab
+                if min_t.is_nan() || max_t.is_nan() {
+                    return (f32::NEG_INFINITY, f32::INFINITY);
+                }
+
+                (min_t.min(max_t), min_t.max(max_t))
+            })
+            .collect();
+
+        // The ray enters through the face of the last slab it enters, and exits
+        // through the face of the first slab it exits
+        let (entry_axis, entry_t) = (0..3)
+            .map(|axis| (axis, slabs[axis].0))
+            .max_by(|(_, p), (_, q)| p.total_cmp(q))
+            .unwrap();
+        let (exit_axis, exit_t) = (0..3)
+            .map(|axis| (axis, slabs[axis].1))
+            .min_by(|(_, p), (_, q)| p.total_cmp(q))
+            .unwrap();
+
+        if entry_t > exit_t {
             return None;
         }
 
-        if t_min < 0.0 {
-            return Some(t_max);
-        }
-
-        Some(t_min)
+        // When the entry is out of bounds, e.g., behind the ray's origin, the
+        // exit may still be hit from inside
+        let (axis, t) = [(entry_axis, entry_t), (exit_axis, exit_t)]
+            .into_iter()
+            .find(|(_, t)| (t_min..=t_max).contains(t))?;
+
+        let point = other.point_at(t);
+        let mut normal = Vector3::zeros();
+        normal[axis] = if point[axis] - self.min[axis] < self.max[axis] - point[axis] {
+            -1.0
+        } else {
+            1.0
+        };
+
+        Some(Hit::new(
+            other,
+            t,
+            normal,
+            normal,
+            self.uv(&point, axis),
+            None,
+        ))
     }
 }
 
@@ -105,3 +119,43 @@ impl AABB for AABoxGeometry {
         self.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> AABoxGeometry {
+        AABoxGeometry {
+            min: Vector3::repeat(-1.0),
+            max: Vector3::repeat(1.0),
+        }
+    }
+
+    #[test]
+    fn misses_boxes_behind_the_ray() {
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 3.0), Vector3::z());
+
+        assert!(unit_box().intersection(&ray, 0.0, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn hits_the_far_side_from_inside() {
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 0.5), -Vector3::z());
+        let hit = unit_box().intersection(&ray, 0.0, f32::INFINITY).unwrap();
+
+        assert_eq!(hit.t, 1.5);
+        assert!(!hit.front_face);
+        assert_eq!(hit.geometric_normal, Vector3::z());
+    }
+
+    #[test]
+    fn ignores_hits_past_t_max() {
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 3.0), -Vector3::z());
+        let aa_box = unit_box();
+
+        assert!(aa_box.intersection(&ray, 0.0, 1.9).is_none());
+        // The entry is excluded, but not the exit
+        let hit = aa_box.intersection(&ray, 2.5, 5.0).unwrap();
+        assert_eq!(hit.t, 4.0);
+    }
+}
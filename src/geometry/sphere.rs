@@ -1,8 +1,10 @@
-use na::Vector3;
+use std::f32::consts::PI;
+
+use na::{Vector2, Vector3};
 
 use crate::rendering::ray::Ray;
 
-use super::{aa_box::AABoxGeometry, Center, Intersection, NormalAt, AABB};
+use super::{aa_box::AABoxGeometry, Center, Hit, Intersection, AABB};
 
 #[derive(Debug, Clone)]
 pub struct SphereGeometry {
@@ -16,17 +18,28 @@ impl Center for SphereGeometry {
     }
 }
 
-impl NormalAt for SphereGeometry {
-    fn normal_at(&self, point: &Vector3<f32>) -> Vector3<f32> {
-        (point - self.center).normalize()
+impl SphereGeometry {
+    /// Spherical coordinates of a unit normal, with v running from the bottom
+    /// (-y) to the top (+y) of the sphere
+    fn uv(normal: &Vector3<f32>) -> Vector2<f32> {
+        let phi = (-normal.z).atan2(normal.x) + PI;
+        let theta = (-normal.y).clamp(-1.0, 1.0).acos();
+
+        Vector2::new(phi / (2.0 * PI), theta / PI)
+    }
+
+    fn hit(&self, ray: &Ray, t: f32) -> Hit {
+        let normal = (ray.point_at(t) - self.center) / self.radius;
+
+        Hit::new(ray, t, normal, normal, Self::uv(&normal), None)
     }
 }
 
-impl Intersection<Ray, f32> for SphereGeometry {
+impl Intersection<Ray, Hit> for SphereGeometry {
     type Argument = Ray;
-    type Output = f32;
+    type Output = Hit;
 
-    fn intersection(&self, other: &Self::Argument) -> Option<Self::Output> {
+    fn intersection(&self, other: &Self::Argument, t_min: f32, t_max: f32) -> Option<Self::Output> {
         // a, b, and c are standard quadratic equation coefficients
 
         let a = other.direction().dot(&other.direction());
@@ -36,7 +49,7 @@ impl Intersection<Ray, f32> for SphereGeometry {
             return None;
         }
 
-        let u: Vector3<Self::Output> = other.origin - self.center;
+        let u: Vector3<f32> = other.origin - self.center;
         let b = other.direction().dot(&u);
         let c = u.dot(&u) - self.radius.powi(2);
 
@@ -47,14 +60,16 @@ impl Intersection<Ray, f32> for SphereGeometry {
             return None;
         }
 
-        // Just grazes surface (i.e., tangent to surface)
-        if discriminant == 0.0 {
-            return Some(-b);
-        }
-
         // Since second_term is always positive, (-) root is always smaller,
-        // i.e., closer to camera
-        Some(-b - discriminant.sqrt())
+        // i.e., closer to camera. When it's out of bounds, e.g., behind the
+        // ray's origin, the (+) root may still be hit from inside. When
+        // the ray just grazes the surface, both roots are the same.
+        let second_term = discriminant.sqrt();
+
+        [-b - second_term, -b + second_term]
+            .into_iter()
+            .find(|t| (t_min..=t_max).contains(t))
+            .map(|t| self.hit(other, t))
     }
 }
 
@@ -74,3 +89,47 @@ impl AABB for SphereGeometry {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_sphere() -> SphereGeometry {
+        SphereGeometry {
+            center: Vector3::zeros(),
+            radius: 1.0,
+        }
+    }
+
+    #[test]
+    fn misses_spheres_behind_the_ray() {
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 3.0), Vector3::z());
+
+        assert!(unit_sphere()
+            .intersection(&ray, 0.0, f32::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn hits_the_far_side_from_inside() {
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 0.5), -Vector3::z());
+        let hit = unit_sphere()
+            .intersection(&ray, 0.0, f32::INFINITY)
+            .unwrap();
+
+        assert!((hit.t - 1.5).abs() < 1e-6);
+        assert!(!hit.front_face);
+        assert!((hit.geometric_normal - Vector3::z()).norm() < 1e-6);
+    }
+
+    #[test]
+    fn ignores_hits_past_t_max() {
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 3.0), -Vector3::z());
+        let sphere = unit_sphere();
+
+        assert!(sphere.intersection(&ray, 0.0, 1.9).is_none());
+        // The near root is excluded, but not the far one
+        let hit = sphere.intersection(&ray, 2.5, 5.0).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-6);
+    }
+}
@@ -1,14 +1,61 @@
-use na::{Matrix3, Vector3};
+use std::collections::HashMap;
+
+use na::{Matrix3, Vector2, Vector3};
 
 use crate::rendering::ray::Ray;
 
-use super::{aa_box::AABoxGeometry, Center, Intersection, NormalAt, AABB};
+use super::{aa_box::AABoxGeometry, Center, Geometry, Hit, Intersection, AABB};
 
 #[derive(Debug, Clone)]
 pub struct TriangleGeometry {
     pub a: Vector3<f32>,
     pub b: Vector3<f32>,
     pub c: Vector3<f32>,
+    /// Vertex normals for a, b, and c, which are interpolated for smooth
+    /// shading. Without them, the triangle is flat shaded.
+    pub normals: Option<[Vector3<f32>; 3]>,
+    /// Texture coordinates for a, b, and c, which are interpolated across the
+    /// triangle
+    pub uvs: Option<[Vector2<f32>; 3]>,
+}
+
+impl TriangleGeometry {
+    pub fn face_normal(&self) -> Vector3<f32> {
+        (self.b - self.a).cross(&(self.c - self.a)).normalize()
+    }
+
+    /// Returns t along with the barycentric weights of a, b, and c at the hit
+    pub fn barycentric_intersection(&self, ray: &Ray) -> Option<(f32, Vector3<f32>)> {
+        Matrix3::from_columns(&[self.b - self.a, self.c - self.a, -ray.direction()])
+            .lu()
+            .solve(&(ray.origin - self.a))
+            .map(|uvt| [uvt[0], uvt[1], uvt[2]])
+            .filter(|[u, v, ..]| {
+                let w = 1.0 - u - v;
+
+                [*u, *v, w].iter().all(|x| (0.0..=1.0).contains(x))
+            })
+            .map(|[u, v, t]| (t, Vector3::new(1.0 - u - v, u, v)))
+    }
+
+    /// Interpolates the vertex normals, or falls back to the face normal
+    pub fn interpolated_normal(&self, barycentrics: &Vector3<f32>) -> Vector3<f32> {
+        match self.normals {
+            Some([na, nb, nc]) => {
+                (na * barycentrics.x + nb * barycentrics.y + nc * barycentrics.z).normalize()
+            }
+            None => self.face_normal(),
+        }
+    }
+
+    /// Interpolates the vertex UVs, or falls back to the weights of b and c,
+    /// which are the natural parameterization of the triangle
+    pub fn interpolated_uv(&self, barycentrics: &Vector3<f32>) -> Vector2<f32> {
+        match self.uvs {
+            Some([ta, tb, tc]) => ta * barycentrics.x + tb * barycentrics.y + tc * barycentrics.z,
+            None => Vector2::new(barycentrics.y, barycentrics.z),
+        }
+    }
 }
 
 impl Center for TriangleGeometry {
@@ -21,27 +68,23 @@ impl Center for TriangleGeometry {
     }
 }
 
-impl NormalAt for TriangleGeometry {
-    fn normal_at(&self, _: &Vector3<f32>) -> Vector3<f32> {
-        (self.b - self.a).cross(&(self.c - self.a)).normalize()
-    }
-}
-
-impl Intersection<Ray, f32> for TriangleGeometry {
+impl Intersection<Ray, Hit> for TriangleGeometry {
     type Argument = Ray;
-    type Output = f32;
+    type Output = Hit;
 
-    fn intersection(&self, other: &Self::Argument) -> Option<Self::Output> {
-        Matrix3::from_columns(&[self.b - self.a, self.c - self.a, -other.direction()])
-            .lu()
-            .solve(&(other.origin - self.a))
-            .map(|uvt| [uvt[0], uvt[1], uvt[2]])
-            .filter(|[u, v, ..]| {
-                let w = 1.0 - u - v;
+    fn intersection(&self, other: &Self::Argument, t_min: f32, t_max: f32) -> Option<Self::Output> {
+        let (t, barycentrics) = self
+            .barycentric_intersection(other)
+            .filter(|(t, _)| (t_min..=t_max).contains(t))?;
 
-                u >= &0.0 && u <= &1.0 && v >= &0.0 && v <= &1.0 && w >= 0.0 && w <= 1.0
-            })
-            .map(|[.., t]| t)
+        Some(Hit::new(
+            other,
+            t,
+            self.face_normal(),
+            self.interpolated_normal(&barycentrics),
+            self.interpolated_uv(&barycentrics),
+            Some(barycentrics),
+        ))
     }
 }
 
@@ -61,3 +104,153 @@ impl AABB for TriangleGeometry {
         }
     }
 }
+
+/// Fills in vertex normals for every triangle in the mesh that lacks them.
+/// Each vertex normal is the average of the face normals around that vertex,
+/// weighted by the angle each face makes at the vertex, so that the result
+/// doesn't depend on how the surface was triangulated. Vertices are shared
+/// only where triangles have exactly the same position.
+pub fn smooth_normals(mesh: &mut [Geometry]) {
+    // Adding 0 turns -0 into 0, which exporters often write for either
+    let key = |v: &Vector3<f32>| [v.x, v.y, v.z].map(|x| (x + 0.0).to_bits());
+    let mut sums: HashMap<[u32; 3], Vector3<f32>> = HashMap::new();
+
+    for geometry in mesh.iter() {
+        let Geometry::Triangle(triangle) = geometry else {
+            continue;
+        };
+        let face_normal = triangle.face_normal();
+        if !face_normal.iter().all(|x| x.is_finite()) {
+            continue;
+        }
+
+        let corners = [triangle.a, triangle.b, triangle.c];
+        for k in 0..3 {
+            let to_next = corners[(k + 1) % 3] - corners[k];
+            let to_previous = corners[(k + 2) % 3] - corners[k];
+            let angle = to_next.angle(&to_previous);
+
+            *sums.entry(key(&corners[k])).or_insert(Vector3::zeros()) += face_normal * angle;
+        }
+    }
+
+    for geometry in mesh.iter_mut() {
+        let Geometry::Triangle(triangle) = geometry else {
+            continue;
+        };
+        if triangle.normals.is_some() {
+            continue;
+        }
+
+        let face_normal = triangle.face_normal();
+        triangle.normals = Some([triangle.a, triangle.b, triangle.c].map(|corner| {
+            sums.get(&key(&corner))
+                .and_then(|sum| sum.try_normalize(f32::EPSILON))
+                .unwrap_or(face_normal)
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> Geometry {
+        Geometry::Triangle(TriangleGeometry {
+            a: Vector3::from(a),
+            b: Vector3::from(b),
+            c: Vector3::from(c),
+            normals: None,
+            uvs: None,
+        })
+    }
+
+    fn normals(geometry: &Geometry) -> [Vector3<f32>; 3] {
+        match geometry {
+            Geometry::Triangle(triangle) => triangle.normals.unwrap(),
+            _ => panic!("expected a triangle"),
+        }
+    }
+
+    #[test]
+    fn smooths_across_negative_zero() {
+        let mut mesh = [
+            triangle([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            triangle([-0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 1.0]),
+        ];
+        smooth_normals(&mut mesh);
+
+        assert_eq!(normals(&mesh[0])[0], normals(&mesh[1])[0]);
+    }
+
+    #[test]
+    fn weights_face_normals_by_corner_angle() {
+        // Normals of +z with a right angle at the origin, and +y with a 45°
+        // angle there
+        let mut mesh = [
+            triangle([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            triangle([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 1.0]),
+        ];
+        smooth_normals(&mut mesh);
+
+        let expected = Vector3::new(0.0, 1.0, 2.0).normalize();
+        for geometry in &mesh {
+            assert!((normals(geometry)[0] - expected).norm() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn keeps_existing_normals() {
+        let mut mesh = [Geometry::Triangle(TriangleGeometry {
+            a: Vector3::zeros(),
+            b: Vector3::x(),
+            c: Vector3::y(),
+            normals: Some([Vector3::x(); 3]),
+            uvs: None,
+        })];
+        smooth_normals(&mut mesh);
+
+        assert_eq!(normals(&mesh[0]), [Vector3::x(); 3]);
+    }
+
+    fn unit_triangle() -> TriangleGeometry {
+        TriangleGeometry {
+            a: Vector3::new(-1.0, -1.0, 0.0),
+            b: Vector3::new(1.0, -1.0, 0.0),
+            c: Vector3::new(0.0, 1.0, 0.0),
+            normals: None,
+            uvs: None,
+        }
+    }
+
+    #[test]
+    fn misses_triangles_behind_the_ray() {
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 1.0), Vector3::z());
+
+        assert!(unit_triangle()
+            .intersection(&ray, 0.0, f32::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn hits_back_faces() {
+        // The vertices wind counterclockwise seen from +z, so -z is the back
+        let ray = Ray::new(Vector3::new(0.0, 0.0, -1.0), Vector3::z());
+        let hit = unit_triangle()
+            .intersection(&ray, 0.0, f32::INFINITY)
+            .unwrap();
+
+        assert!((hit.t - 1.0).abs() < 1e-6);
+        assert!(!hit.front_face);
+        assert!((hit.geometric_normal + Vector3::z()).norm() < 1e-6);
+    }
+
+    #[test]
+    fn ignores_hits_past_t_max() {
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 1.0), -Vector3::z());
+        let triangle = unit_triangle();
+
+        assert!(triangle.intersection(&ray, 0.0, 0.9).is_none());
+        assert!(triangle.intersection(&ray, 0.0, 1.1).is_some());
+    }
+}
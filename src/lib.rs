@@ -4,6 +4,7 @@ pub mod bvh;
 pub mod cameras;
 pub mod geometry;
 pub mod lighting;
+pub mod loaders;
 pub mod materials;
 pub mod objects;
 pub mod rendering;
@@ -2,7 +2,9 @@ use std::{iter::Sum, ops};
 
 use na::Vector3;
 
-#[derive(Debug)]
+/// Linear radiance, which isn't limited to [0, 1] so that shading doesn't lose
+/// energy. It's only brought into displayable range by `ToneMapping`.
+#[derive(Debug, Clone, Copy)]
 pub struct Color {
     red: f32,
     green: f32,
@@ -11,11 +13,13 @@ pub struct Color {
 
 impl Color {
     pub fn new(red: f32, green: f32, blue: f32) -> Self {
-        Self {
-            red: red.clamp(0.0, 1.0),
-            green: green.clamp(0.0, 1.0),
-            blue: blue.clamp(0.0, 1.0),
-        }
+        Self { red, green, blue }
+    }
+
+    /// Decodes sRGB encoded channels, e.g., from a colour picker or an 8 bit
+    /// image, into linear values
+    pub fn from_srgb(red: f32, green: f32, blue: f32) -> Self {
+        Self::new(red, green, blue).map(srgb_to_linear)
     }
 
     pub fn new_black() -> Self {
@@ -23,24 +27,53 @@ impl Color {
     }
 
     pub fn red(&self) -> f32 {
-        return self.red;
+        self.red
     }
 
     pub fn green(&self) -> f32 {
-        return self.green;
+        self.green
     }
 
     pub fn blue(&self) -> f32 {
-        return self.blue;
+        self.blue
+    }
+
+    /// Encodes linear channels with the sRGB transfer function
+    pub fn to_srgb(&self) -> Self {
+        self.map(linear_to_srgb)
     }
 
+    /// sRGB encoded bytes, as expected by image files. Channels outside of
+    /// [0, 1] are clamped, so tone map HDR colours first.
     pub fn as_8_bit_array(&self) -> [u8; 3] {
+        let srgb = self.map(|c| c.clamp(0.0, 1.0)).to_srgb();
+
         [
-            (self.red * 255.0) as u8,
-            (self.green * 255.0) as u8,
-            (self.blue * 255.0) as u8,
+            (srgb.red * 255.0).round() as u8,
+            (srgb.green * 255.0).round() as u8,
+            (srgb.blue * 255.0).round() as u8,
         ]
     }
+
+    fn map(&self, f: impl Fn(f32) -> f32) -> Self {
+        Self::new(f(self.red), f(self.green), f(self.blue))
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
 }
 
 impl ops::Add for Color {
@@ -89,13 +122,38 @@ impl ops::Div<f32> for Color {
     }
 }
 
-impl Copy for Color {}
-impl Clone for Color {
-    fn clone(&self) -> Self {
-        Color::new(self.red, self.green, self.blue)
+/// Operator that compresses HDR colours into [0, 1] for display
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ToneMapping {
+    /// Cuts off anything brighter than 1, which matches rendering without HDR
+    #[default]
+    Clamp,
+    /// c / (1 + c), which never quite reaches white
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve, with a toe and shoulder
+    AcesFilmic,
+}
+
+impl ToneMapping {
+    /// Scales the colour by 2^exposure, then compresses it
+    pub fn apply(&self, color: Color, exposure: f32) -> Color {
+        let exposed = color * exposure.exp2();
+
+        match self {
+            ToneMapping::Clamp => exposed.map(|c| c.clamp(0.0, 1.0)),
+            ToneMapping::Reinhard => exposed.map(|c| {
+                let c = c.max(0.0);
+                c / (1.0 + c)
+            }),
+            ToneMapping::AcesFilmic => exposed.map(|c| {
+                let c = c.max(0.0);
+                ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }),
+        }
     }
 }
 
+#[derive(Debug)]
 pub struct Light {
     pub position: Vector3<f32>,
     pub color: Color,
@@ -0,0 +1,47 @@
+pub mod gltf;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+
+use std::{error::Error, fmt, io};
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    Invalid(String),
+}
+
+impl LoadError {
+    fn parse(line: usize, message: impl Into<String>) -> Self {
+        LoadError::Parse {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "{}", error),
+            LoadError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            LoadError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(error) => Some(error),
+            LoadError::Parse { .. } | LoadError::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> Self {
+        LoadError::Io(error)
+    }
+}
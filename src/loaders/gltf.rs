@@ -0,0 +1,467 @@
+use std::{fs, path::Path};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use gltf::{buffer::Source, camera::Projection, khr_lights_punctual::Kind, mesh::Mode, Gltf, Node};
+use na::{Matrix4, Point3, UnitQuaternion, Vector3};
+
+use crate::{
+    cameras::{self, Camera},
+    geometry::{triangle::TriangleGeometry, Geometry},
+    lighting::{Color, Light},
+    materials::Material,
+    objects::Object,
+};
+
+use super::LoadError;
+
+const SUPPORTED_EXTENSIONS: [&str; 1] = ["KHR_lights_punctual"];
+
+#[derive(Debug)]
+pub struct GltfMesh {
+    pub name: String,
+    /// Index into `GltfScene::materials`, if the primitive has a material
+    pub material: Option<usize>,
+    /// Triangles already transformed into world space
+    pub mesh: Vec<Geometry>,
+}
+
+#[derive(Debug)]
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<Material>,
+    pub cameras: Vec<Camera>,
+    /// Colors are scaled by each light's intensity relative to the brightest
+    /// light, which keeps the balance between lights without falloff
+    pub lights: Vec<Light>,
+    /// Features of the file that were ignored or approximated
+    pub warnings: Vec<String>,
+}
+
+impl GltfScene {
+    /// Pairs every mesh with its material, falling back to `default_material`
+    /// for primitives without one
+    pub fn objects<'a>(&'a self, default_material: &'a Material) -> Vec<Object<'a>> {
+        self.meshes
+            .iter()
+            .map(|gltf_mesh| Object {
+                mesh: &gltf_mesh.mesh,
+                material: gltf_mesh
+                    .material
+                    .map(|index| &self.materials[index])
+                    .unwrap_or(default_material),
+            })
+            .collect()
+    }
+}
+
+/// Loads a .gltf or .glb file, resolving external buffers relative to the
+/// file's directory
+pub fn load_gltf(path: impl AsRef<Path>) -> Result<GltfScene, LoadError> {
+    let path = path.as_ref();
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    parse_gltf(&fs::read(path)?, base_dir)
+}
+
+pub fn parse_gltf(bytes: &[u8], base_dir: &Path) -> Result<GltfScene, LoadError> {
+    let gltf = Gltf::from_slice(bytes).map_err(|error| LoadError::Invalid(error.to_string()))?;
+    let buffers = load_buffers(&gltf, base_dir)?;
+
+    let mut scene = GltfScene {
+        meshes: vec![],
+        materials: gltf.materials().map(convert_material).collect(),
+        cameras: vec![],
+        lights: vec![],
+        warnings: vec![],
+    };
+
+    for extension in gltf.extensions_used() {
+        if !SUPPORTED_EXTENSIONS.contains(&extension) {
+            scene
+                .warnings
+                .push(format!("extension {} is not supported", extension));
+        }
+    }
+
+    for material in gltf.materials() {
+        material_warnings(&material, &mut scene.warnings);
+    }
+
+    let Some(gltf_scene) = gltf.default_scene().or(gltf.scenes().next()) else {
+        scene.warnings.push("file contains no scenes".to_string());
+        return Ok(scene);
+    };
+
+    if gltf.scenes().len() > 1 {
+        scene.warnings.push(format!(
+            "file contains {} scenes, only scene {} was loaded",
+            gltf.scenes().len(),
+            gltf_scene.index()
+        ));
+    }
+
+    for node in gltf_scene.nodes() {
+        visit_node(&node, &Matrix4::identity(), &buffers, &mut scene);
+    }
+
+    // Intensities are physical units, e.g., candela, which would be far too
+    // bright for lights without falloff
+    let brightest = gltf
+        .lights()
+        .into_iter()
+        .flatten()
+        .filter(|light| !matches!(light.kind(), Kind::Directional))
+        .map(|light| light.intensity())
+        .fold(0.0, f32::max);
+    if brightest > 0.0 {
+        for light in &mut scene.lights {
+            light.color = light.color / brightest;
+        }
+    }
+
+    Ok(scene)
+}
+
+fn load_buffers(gltf: &Gltf, base_dir: &Path) -> Result<Vec<Vec<u8>>, LoadError> {
+    gltf.buffers()
+        .map(|buffer| {
+            let data = match buffer.source() {
+                Source::Bin => gltf.blob.clone().ok_or(LoadError::Invalid(
+                    "buffer refers to a missing GLB binary chunk".to_string(),
+                ))?,
+                Source::Uri(uri) if uri.starts_with("data:") => {
+                    let encoded = uri
+                        .split_once(";base64,")
+                        .map(|(_, encoded)| encoded)
+                        .ok_or(LoadError::Invalid(
+                            "only base64 data URIs are supported".to_string(),
+                        ))?;
+
+                    STANDARD
+                        .decode(encoded)
+                        .map_err(|error| LoadError::Invalid(error.to_string()))?
+                }
+                Source::Uri(uri) => fs::read(base_dir.join(uri))?,
+            };
+
+            if data.len() < buffer.length() {
+                return Err(LoadError::Invalid(format!(
+                    "buffer {} is {} bytes, expected {}",
+                    buffer.index(),
+                    data.len(),
+                    buffer.length()
+                )));
+            }
+
+            Ok(data)
+        })
+        .collect()
+}
+
+fn visit_node(
+    node: &Node,
+    parent_transform: &Matrix4<f32>,
+    buffers: &[Vec<u8>],
+    scene: &mut GltfScene,
+) {
+    let transform = parent_transform * Matrix4::from(node.transform().matrix());
+    let name = node
+        .name()
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("node {}", node.index()));
+
+    if let Some(mesh) = node.mesh() {
+        if node.skin().is_some() {
+            scene
+                .warnings
+                .push(format!("{}: skinning is not supported", name));
+        }
+
+        for primitive in mesh.primitives() {
+            if primitive.morph_targets().len() > 0 {
+                scene
+                    .warnings
+                    .push(format!("{}: morph targets are not supported", name));
+            }
+
+            match read_triangles(&primitive, &transform, buffers) {
+                Ok(triangles) => scene.meshes.push(GltfMesh {
+                    name: name.clone(),
+                    material: primitive.material().index(),
+                    mesh: triangles,
+                }),
+                Err(message) => scene.warnings.push(format!("{}: {}", name, message)),
+            }
+        }
+    }
+
+    if let Some(camera) = node.camera() {
+        // glTF cameras also look down their negative z axis, so only scaling
+        // has to be removed from the transform
+        let rotation = transform.fixed_view::<3, 3>(0, 0).into_owned();
+        let mut loaded = Camera {
+            position: transform.transform_point(&Point3::origin()).coords,
+            orientation: UnitQuaternion::from_matrix(&rotation),
+            ..Camera::default()
+        };
+
+        match camera.projection() {
+            Projection::Perspective(perspective) => {
+                loaded.fov = perspective.yfov().to_degrees();
+                loaded.focal_length = perspective.znear();
+            }
+            // ymag is half the height of the view. The aspect ratio comes from
+            // the rendered image instead of xmag.
+            Projection::Orthographic(orthographic) => {
+                loaded.projection = cameras::Projection::Orthographic {
+                    view_height: orthographic.ymag() * 2.0,
+                };
+            }
+        }
+
+        scene.cameras.push(loaded);
+    }
+
+    if let Some(light) = node.light() {
+        let [r, g, b] = light.color();
+        let position = transform.transform_point(&Point3::origin()).coords;
+
+        match light.kind() {
+            Kind::Point => {}
+            Kind::Spot { .. } => scene
+                .warnings
+                .push(format!("{}: spot light is treated as a point light", name)),
+            Kind::Directional => {
+                scene
+                    .warnings
+                    .push(format!("{}: directional lights are not supported", name));
+                return visit_children(node, &transform, buffers, scene);
+            }
+        }
+
+        scene.lights.push(Light {
+            position,
+            color: Color::new(r, g, b) * light.intensity(),
+        });
+    }
+
+    visit_children(node, &transform, buffers, scene);
+}
+
+fn visit_children(
+    node: &Node,
+    transform: &Matrix4<f32>,
+    buffers: &[Vec<u8>],
+    scene: &mut GltfScene,
+) {
+    for child in node.children() {
+        visit_node(&child, transform, buffers, scene);
+    }
+}
+
+fn read_triangles(
+    primitive: &gltf::Primitive,
+    transform: &Matrix4<f32>,
+    buffers: &[Vec<u8>],
+) -> Result<Vec<Geometry>, String> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+
+    let positions: Vec<Vector3<f32>> = reader
+        .read_positions()
+        .ok_or("primitive has no positions")?
+        .map(|p| transform.transform_point(&Point3::from(p)).coords)
+        .collect();
+
+    // Normals transform by the inverse transpose, so they stay perpendicular
+    // under non-uniform scaling
+    let normal_transform = transform
+        .fixed_view::<3, 3>(0, 0)
+        .try_inverse()
+        .unwrap_or_default()
+        .transpose();
+    let normals: Option<Vec<Vector3<f32>>> = reader.read_normals().map(|normals| {
+        normals
+            .map(|n| (normal_transform * Vector3::from(n)).normalize())
+            .collect()
+    });
+
+    let indices: Vec<usize> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().map(|index| index as usize).collect(),
+        None => (0..positions.len()).collect(),
+    };
+
+    if let Some(index) = indices.iter().find(|index| **index >= positions.len()) {
+        return Err(format!("index {} is out of range", index));
+    }
+
+    let corners: Vec<[usize; 3]> = match primitive.mode() {
+        Mode::Triangles => indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect(),
+        // Every other triangle of a strip is wound the other way
+        Mode::TriangleStrip => (0..indices.len().saturating_sub(2))
+            .map(|k| match k % 2 {
+                0 => [indices[k], indices[k + 1], indices[k + 2]],
+                _ => [indices[k + 1], indices[k], indices[k + 2]],
+            })
+            .collect(),
+        Mode::TriangleFan => (1..indices.len().saturating_sub(1))
+            .map(|k| [indices[0], indices[k], indices[k + 1]])
+            .collect(),
+        mode => return Err(format!("{:?} primitives are not supported", mode)),
+    };
+
+    // A mirroring transform flips the winding order, and so the normals
+    let is_mirrored = transform.fixed_view::<3, 3>(0, 0).determinant() < 0.0;
+
+    Ok(corners
+        .into_iter()
+        .map(|[a, b, c]| {
+            let (b, c) = if is_mirrored { (c, b) } else { (b, c) };
+
+            Geometry::Triangle(TriangleGeometry {
+                a: positions[a],
+                b: positions[b],
+                c: positions[c],
+                normals: normals
+                    .as_ref()
+                    .filter(|normals| normals.len() == positions.len())
+                    .map(|normals| [normals[a], normals[b], normals[c]]),
+                uvs: None,
+            })
+        })
+        .collect())
+}
+
+/// Approximates a metallic-roughness material with the Phong parameters
+fn convert_material(material: gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, _] = pbr.base_color_factor();
+    let base_color = Vector3::new(r, g, b);
+    let metallic = pbr.metallic_factor();
+    let roughness = pbr.roughness_factor();
+
+    // Dielectrics reflect about 4% specularly, while metals tint the reflection
+    let diffuse = base_color * (1.0 - metallic);
+    let specular = Vector3::repeat(0.04).lerp(&base_color, metallic);
+
+    // Blinn-Phong exponent equivalent to a GGX distribution of this roughness
+    let alpha = roughness.powi(2).max(1e-3);
+    let shininess = (2.0 / alpha.powi(2) - 2.0).clamp(0.0, 1000.0);
+
+    // Emission has no direct equivalent, but ambient is the closest constant term
+    let [er, eg, eb] = material.emissive_factor();
+
+    Material {
+        ambient_color: Color::new(er, eg, eb),
+        diffuse_color: Color::new(diffuse.x, diffuse.y, diffuse.z),
+        specular_color: Color::new(specular.x, specular.y, specular.z),
+        shininess: shininess.round() as i32,
+        reflectance: metallic * (1.0 - roughness),
+        dielectric: None,
+    }
+}
+
+fn material_warnings(material: &gltf::Material, warnings: &mut Vec<String>) {
+    let name = material
+        .name()
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("material {}", material.index().unwrap_or_default()));
+    let pbr = material.pbr_metallic_roughness();
+
+    if pbr.base_color_texture().is_some()
+        || pbr.metallic_roughness_texture().is_some()
+        || material.normal_texture().is_some()
+        || material.occlusion_texture().is_some()
+        || material.emissive_texture().is_some()
+    {
+        warnings.push(format!("{}: textures are not supported", name));
+    }
+
+    if material.alpha_mode() != gltf::material::AlphaMode::Opaque {
+        warnings.push(format!(
+            "{}: alpha mode {:?} is treated as opaque",
+            name,
+            material.alpha_mode()
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A triangle scaled and moved by its node, a camera, a point light and a
+    /// dimmer spot light, with a single buffer embedded as a data URI
+    const SCENE: &str = r#"{
+        "asset": { "version": "2.0" },
+        "extensionsUsed": ["KHR_lights_punctual", "KHR_materials_clearcoat"],
+        "extensions": {
+            "KHR_lights_punctual": {
+                "lights": [
+                    { "type": "point", "color": [1.0, 0.5, 0.0], "intensity": 20.0 },
+                    { "type": "spot", "intensity": 5.0, "spot": {} }
+                ]
+            }
+        },
+        "scene": 0,
+        "scenes": [{ "nodes": [0, 1, 2, 3] }],
+        "nodes": [
+            {
+                "name": "triangle", "mesh": 0,
+                "translation": [0.0, 0.0, -1.0], "scale": [2.0, 2.0, 2.0]
+            },
+            { "camera": 0, "translation": [0.0, 1.0, 5.0] },
+            {
+                "translation": [3.0, 4.0, 0.0],
+                "extensions": { "KHR_lights_punctual": { "light": 0 } }
+            },
+            { "name": "spot", "extensions": { "KHR_lights_punctual": { "light": 1 } } }
+        ],
+        "cameras": [{ "type": "perspective", "perspective": { "yfov": 0.5, "znear": 0.1 } }],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+        "accessors": [{
+            "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+            "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0]
+        }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+        "buffers": [{
+            "byteLength": 36,
+            "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA"
+        }]
+    }"#;
+
+    #[test]
+    fn loads_embedded_scene() {
+        let scene = parse_gltf(SCENE.as_bytes(), Path::new("")).unwrap();
+
+        assert_eq!(scene.meshes.len(), 1);
+        let Geometry::Triangle(triangle) = &scene.meshes[0].mesh[0] else {
+            panic!("expected a triangle");
+        };
+        assert_eq!(triangle.a, Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(triangle.b, Vector3::new(2.0, 0.0, -1.0));
+        assert_eq!(triangle.c, Vector3::new(0.0, 2.0, -1.0));
+
+        assert_eq!(scene.cameras.len(), 1);
+        assert_eq!(scene.cameras[0].position, Vector3::new(0.0, 1.0, 5.0));
+        assert!((scene.cameras[0].fov - 0.5f32.to_degrees()).abs() < 1e-4);
+
+        // Intensities are relative to the brightest light
+        assert_eq!(scene.lights.len(), 2);
+        assert_eq!(scene.lights[0].position, Vector3::new(3.0, 4.0, 0.0));
+        let color = scene.lights[0].color;
+        assert_eq!([color.red(), color.green(), color.blue()], [1.0, 0.5, 0.0]);
+        let color = scene.lights[1].color;
+        assert_eq!([color.red(), color.green(), color.blue()], [0.25; 3]);
+
+        assert_eq!(
+            scene.warnings,
+            [
+                "extension KHR_materials_clearcoat is not supported",
+                "spot: spot light is treated as a point light",
+            ]
+        );
+    }
+}
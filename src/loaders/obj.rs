@@ -0,0 +1,480 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use na::{Vector2, Vector3};
+
+use crate::{
+    geometry::{triangle::TriangleGeometry, Geometry},
+    lighting::Color,
+    materials::Material,
+    objects::Object,
+};
+
+use super::LoadError;
+
+#[derive(Debug)]
+pub struct ObjMesh {
+    pub name: String,
+    pub material: Option<String>,
+    pub mesh: Vec<Geometry>,
+}
+
+#[derive(Debug)]
+pub struct ObjScene {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: HashMap<String, Material>,
+}
+
+impl ObjScene {
+    /// Pairs every mesh with its material, falling back to `default_material`
+    /// when the mesh has none or its material wasn't defined in any MTL file
+    pub fn objects<'a>(&'a self, default_material: &'a Material) -> Vec<Object<'a>> {
+        self.meshes
+            .iter()
+            .map(|obj_mesh| Object {
+                mesh: &obj_mesh.mesh,
+                material: obj_mesh
+                    .material
+                    .as_ref()
+                    .and_then(|name| self.materials.get(name))
+                    .unwrap_or(default_material),
+            })
+            .collect()
+    }
+}
+
+/// Loads an OBJ file along with any MTL files it references, which are
+/// resolved relative to the OBJ file's directory
+pub fn load_obj(path: impl AsRef<Path>) -> Result<ObjScene, LoadError> {
+    let path = path.as_ref();
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    parse_obj(BufReader::new(File::open(path)?), base_dir)
+}
+
+pub fn parse_obj(reader: impl BufRead, base_dir: &Path) -> Result<ObjScene, LoadError> {
+    let mut positions: Vec<Vector3<f32>> = vec![];
+    let mut normals: Vec<Vector3<f32>> = vec![];
+    let mut uvs: Vec<Vector2<f32>> = vec![];
+
+    let mut meshes: Vec<ObjMesh> = vec![];
+    let mut materials = HashMap::new();
+    let mut current = ObjMesh {
+        name: String::new(),
+        material: None,
+        mesh: vec![],
+    };
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line?;
+        let mut tokens = statement(&line);
+
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(parse_vector(&args, line_number)?),
+            "vn" => normals.push(parse_vector(&args, line_number)?),
+            "vt" => {
+                let uv = parse_floats(&args, 1, 3, line_number)?;
+                uvs.push(Vector2::new(uv[0], uv.get(1).copied().unwrap_or(0.0)));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(LoadError::parse(
+                        line_number,
+                        "face must have at least 3 vertices",
+                    ));
+                }
+
+                let vertices = args
+                    .iter()
+                    .map(|arg| {
+                        parse_face_vertex(arg, [positions.len(), uvs.len(), normals.len()])
+                            .map_err(|message| LoadError::parse(line_number, message))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // Fan triangulation, which is exact for convex polygons
+                for k in 1..vertices.len() - 1 {
+                    let corners = [&vertices[0], &vertices[k], &vertices[k + 1]];
+
+                    // Smooth shading needs a normal at every corner
+                    let normals = match corners.map(|vertex| vertex.normal) {
+                        [Some(na), Some(nb), Some(nc)] => {
+                            Some([normals[na], normals[nb], normals[nc]].map(|n| n.normalize()))
+                        }
+                        _ => None,
+                    };
+                    let uvs = match corners.map(|vertex| vertex.uv) {
+                        [Some(ta), Some(tb), Some(tc)] => Some([uvs[ta], uvs[tb], uvs[tc]]),
+                        _ => None,
+                    };
+
+                    current.mesh.push(Geometry::Triangle(TriangleGeometry {
+                        a: positions[corners[0].position],
+                        b: positions[corners[1].position],
+                        c: positions[corners[2].position],
+                        normals,
+                        uvs,
+                    }));
+                }
+            }
+            "o" | "g" => {
+                let material = current.material.clone();
+                start_mesh(&mut meshes, &mut current, args.join(" "), material);
+            }
+            "usemtl" => {
+                let material = Some(args.join(" "));
+                if material != current.material {
+                    let name = current.name.clone();
+                    start_mesh(&mut meshes, &mut current, name, material);
+                }
+            }
+            "mtllib" => {
+                for file in args {
+                    let mtl_path = base_dir.join(file);
+                    let mtl_materials = load_mtl(&mtl_path).map_err(|error| match error {
+                        LoadError::Parse { line, message } => LoadError::Parse {
+                            line,
+                            message: format!("in {}: {}", mtl_path.display(), message),
+                        },
+                        error => error,
+                    })?;
+                    materials.extend(mtl_materials);
+                }
+            }
+            // Smoothing groups, lines, points, and free-form geometry aren't used
+            _ => {}
+        }
+    }
+
+    if !current.mesh.is_empty() {
+        meshes.push(current);
+    }
+
+    Ok(ObjScene { meshes, materials })
+}
+
+pub fn load_mtl(path: impl AsRef<Path>) -> Result<HashMap<String, Material>, LoadError> {
+    parse_mtl(BufReader::new(File::open(path)?))
+}
+
+pub fn parse_mtl(reader: impl BufRead) -> Result<HashMap<String, Material>, LoadError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Material)> = None;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line?;
+        let mut tokens = statement(&line);
+
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                return Err(LoadError::parse(line_number, "newmtl requires a name"));
+            }
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+            current = Some((args.join(" "), default_material()));
+            continue;
+        }
+
+        let Some((_, material)) = current.as_mut() else {
+            if matches!(keyword, "Ka" | "Kd" | "Ks" | "Ns") {
+                return Err(LoadError::parse(
+                    line_number,
+                    format!("{} before any newmtl", keyword),
+                ));
+            }
+            continue;
+        };
+
+        // Other properties (textures, transparency, illumination models) have
+        // no equivalent in Material
+        match keyword {
+            "Ka" => material.ambient_color = parse_color(&args, line_number)?,
+            "Kd" => material.diffuse_color = parse_color(&args, line_number)?,
+            "Ks" => material.specular_color = parse_color(&args, line_number)?,
+            "Ns" => material.shininess = parse_floats(&args, 1, 1, line_number)?[0].round() as i32,
+            _ => {}
+        }
+    }
+
+    if let Some((name, material)) = current {
+        materials.insert(name, material);
+    }
+
+    Ok(materials)
+}
+
+fn default_material() -> Material {
+    Material {
+        ambient_color: Color::new_black(),
+        diffuse_color: Color::new(0.8, 0.8, 0.8),
+        specular_color: Color::new_black(),
+        shininess: 0,
+        reflectance: 0.0,
+        dielectric: None,
+    }
+}
+
+/// Splits a line into whitespace separated tokens, ignoring comments
+fn statement(line: &str) -> std::str::SplitWhitespace<'_> {
+    line.split('#').next().unwrap_or("").split_whitespace()
+}
+
+fn start_mesh(
+    meshes: &mut Vec<ObjMesh>,
+    current: &mut ObjMesh,
+    name: String,
+    material: Option<String>,
+) {
+    let previous = std::mem::replace(
+        current,
+        ObjMesh {
+            name,
+            material,
+            mesh: vec![],
+        },
+    );
+
+    if !previous.mesh.is_empty() {
+        meshes.push(previous);
+    }
+}
+
+fn parse_floats(
+    args: &[&str],
+    min_count: usize,
+    max_count: usize,
+    line_number: usize,
+) -> Result<Vec<f32>, LoadError> {
+    if args.len() < min_count {
+        return Err(LoadError::parse(
+            line_number,
+            format!(
+                "expected at least {} values, found {}",
+                min_count,
+                args.len()
+            ),
+        ));
+    }
+
+    args.iter()
+        .take(max_count)
+        .map(|arg| {
+            arg.parse::<f32>()
+                .map_err(|_| LoadError::parse(line_number, format!("invalid number `{}`", arg)))
+        })
+        .collect()
+}
+
+fn parse_vector(args: &[&str], line_number: usize) -> Result<Vector3<f32>, LoadError> {
+    // Any optional trailing values (w, vertex colours) are ignored
+    let xyz = parse_floats(args, 3, 3, line_number)?;
+
+    Ok(Vector3::new(xyz[0], xyz[1], xyz[2]))
+}
+
+fn parse_color(args: &[&str], line_number: usize) -> Result<Color, LoadError> {
+    if args
+        .first()
+        .is_some_and(|arg| arg.starts_with("spectral") || *arg == "xyz")
+    {
+        return Err(LoadError::parse(
+            line_number,
+            "only RGB colours are supported",
+        ));
+    }
+
+    // A single value is shorthand for a grey
+    let rgb = parse_floats(args, 1, 3, line_number)?;
+
+    Ok(match rgb[..] {
+        [r, g, b] => Color::new(r, g, b),
+        [grey, ..] => Color::new(grey, grey, grey),
+        [] => unreachable!(),
+    })
+}
+
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Parses `v`, `v/vt`, `v//vn`, or `v/vt/vn`, checking every index against the
+/// number of positions, texture coordinates, and normals defined so far
+fn parse_face_vertex(arg: &str, counts: [usize; 3]) -> Result<FaceVertex, String> {
+    let parts: Vec<&str> = arg.split('/').collect();
+
+    if parts.len() > 3 || parts[0].is_empty() {
+        return Err(format!("invalid face vertex `{}`", arg));
+    }
+
+    let mut indices = [None; 3];
+    for (k, part) in parts.iter().enumerate() {
+        if !part.is_empty() {
+            indices[k] = Some(resolve_index(part, counts[k])?);
+        }
+    }
+
+    Ok(FaceVertex {
+        position: indices[0].unwrap(),
+        uv: indices[1],
+        normal: indices[2],
+    })
+}
+
+/// Converts a 1-based (or negative, relative) OBJ index to a 0-based one
+fn resolve_index(part: &str, count: usize) -> Result<usize, String> {
+    let index = part
+        .parse::<i64>()
+        .map_err(|_| format!("invalid index `{}`", part))?;
+
+    let resolved = match index {
+        1.. => index - 1,
+        ..=-1 => count as i64 + index,
+        0 => return Err("indices start at 1".to_string()),
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("index {} is out of range", index));
+    }
+
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<ObjScene, LoadError> {
+        parse_obj(text.as_bytes(), Path::new(""))
+    }
+
+    fn triangles(mesh: &ObjMesh) -> Vec<&TriangleGeometry> {
+        mesh.mesh
+            .iter()
+            .map(|geometry| match geometry {
+                Geometry::Triangle(triangle) => triangle,
+                _ => panic!("expected a triangle"),
+            })
+            .collect()
+    }
+
+    const SQUARE: &str = "v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+";
+
+    #[test]
+    fn triangulates_polygons_as_fans() {
+        let scene = parse(&format!("{}f 1 2 3 4\n", SQUARE)).unwrap();
+        let triangles = triangles(&scene.meshes[0]);
+
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(
+            [triangles[0].a, triangles[0].b, triangles[0].c],
+            [Vector3::zeros(), Vector3::x(), Vector3::new(1.0, 1.0, 0.0)]
+        );
+        assert_eq!(
+            [triangles[1].a, triangles[1].b, triangles[1].c],
+            [Vector3::zeros(), Vector3::new(1.0, 1.0, 0.0), Vector3::y()]
+        );
+    }
+
+    #[test]
+    fn resolves_negative_indices() {
+        let scene = parse(&format!("{}f -4 -3 -2\n", SQUARE)).unwrap();
+        let triangle = triangles(&scene.meshes[0])[0];
+
+        assert_eq!(
+            [triangle.a, triangle.b, triangle.c],
+            [Vector3::zeros(), Vector3::x(), Vector3::new(1.0, 1.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn reads_normals_without_uvs() {
+        let scene = parse(&format!("{}vn 0 0 2\nf 1//1 2//1 3//1\n", SQUARE)).unwrap();
+        let triangle = triangles(&scene.meshes[0])[0];
+
+        assert_eq!(triangle.normals, Some([Vector3::z(); 3]));
+        assert_eq!(triangle.uvs, None);
+    }
+
+    #[test]
+    fn keeps_texture_coordinates() {
+        let text = format!("{}vt 0 0\nvt 1 0\nvt 1 1\nf 1/1 2/2 3/3\n", SQUARE);
+        let scene = parse(&text).unwrap();
+        let triangle = triangles(&scene.meshes[0])[0];
+
+        assert_eq!(
+            triangle.uvs,
+            Some([Vector2::zeros(), Vector2::x(), Vector2::new(1.0, 1.0)])
+        );
+        assert_eq!(
+            triangle.interpolated_uv(&Vector3::new(0.0, 0.5, 0.5)),
+            Vector2::new(1.0, 0.5)
+        );
+    }
+
+    #[test]
+    fn splits_meshes_on_groups_and_materials() {
+        let text = format!(
+            "{}g first\nf 1 2 3\nusemtl red\nf 1 3 4\ng second\nf 1 2 3\nusemtl red\nf 2 3 4\n",
+            SQUARE
+        );
+        let scene = parse(&text).unwrap();
+
+        let meshes: Vec<(&str, Option<&str>, usize)> = scene
+            .meshes
+            .iter()
+            .map(|mesh| {
+                (
+                    mesh.name.as_str(),
+                    mesh.material.as_deref(),
+                    mesh.mesh.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            meshes,
+            [
+                ("first", None, 1),
+                ("first", Some("red"), 1),
+                ("second", Some("red"), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_line_numbers() {
+        for (text, line) in [
+            (format!("{}f 1 2 5\n", SQUARE), 5),
+            (format!("{}\n# comment\nv 0 zero 0\n", SQUARE), 7),
+            (format!("{}f 1 2\n", SQUARE), 5),
+            (format!("{}f 0 1 2\n", SQUARE), 5),
+        ] {
+            match parse(&text) {
+                Err(LoadError::Parse { line: found, .. }) => assert_eq!(found, line, "{}", text),
+                result => panic!("expected a parse error, got {:?}", result),
+            }
+        }
+    }
+}
@@ -0,0 +1,646 @@
+use std::{fs, path::Path};
+
+use na::Vector3;
+
+use crate::{
+    geometry::{triangle::TriangleGeometry, Geometry},
+    lighting::Color,
+};
+
+use super::LoadError;
+
+/// Triangles, which carry any vertex normals in the file, along with any vertex
+/// colours, stored per triangle corner in the same order as `mesh`
+#[derive(Debug)]
+pub struct PlyMesh {
+    pub mesh: Vec<Geometry>,
+    pub vertex_colors: Option<Vec<[Color; 3]>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl Scalar {
+    /// Largest value of an integer type, or None for floats
+    fn max_value(&self) -> Option<f64> {
+        match self {
+            Scalar::Int8 => Some(i8::MAX as f64),
+            Scalar::UInt8 => Some(u8::MAX as f64),
+            Scalar::Int16 => Some(i16::MAX as f64),
+            Scalar::UInt16 => Some(u16::MAX as f64),
+            Scalar::Int32 => Some(i32::MAX as f64),
+            Scalar::UInt32 => Some(u32::MAX as f64),
+            Scalar::Float32 | Scalar::Float64 => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Vertex {
+    position: Vector3<f32>,
+    normal: Option<Vector3<f32>>,
+    color: Option<Color>,
+}
+
+pub fn load_ply(path: impl AsRef<Path>) -> Result<PlyMesh, LoadError> {
+    parse_ply(&fs::read(path)?)
+}
+
+pub fn parse_ply(bytes: &[u8]) -> Result<PlyMesh, LoadError> {
+    let (format, elements, body_start, header_lines) = parse_header(bytes)?;
+
+    let mut body = match format {
+        Format::Ascii => Body::Ascii {
+            bytes: &bytes[body_start..],
+            offset: 0,
+            line_number: header_lines + 1,
+        },
+        _ => Body::Binary {
+            bytes: &bytes[body_start..],
+            offset: 0,
+            big_endian: format == Format::BinaryBigEndian,
+        },
+    };
+
+    let mut vertices: Vec<Vertex> = vec![];
+    let mut faces: Vec<Vec<usize>> = vec![];
+
+    for element in &elements {
+        for _ in 0..element.count {
+            match element.name.as_str() {
+                "vertex" => vertices.push(read_vertex(&mut body, element)?),
+                "face" => faces.push(read_face(&mut body, element)?),
+                // Other elements (edges, materials) are read only to skip them
+                _ => {
+                    for property in &element.properties {
+                        skip_property(&mut body, property)?;
+                    }
+                }
+            }
+        }
+    }
+
+    let has_normals = vertices.iter().all(|v| v.normal.is_some());
+    let has_colors = vertices.iter().all(|v| v.color.is_some());
+
+    let mut mesh = vec![];
+    let mut vertex_colors = vec![];
+
+    for (face_index, face) in faces.iter().enumerate() {
+        if let Some(index) = face.iter().find(|index| **index >= vertices.len()) {
+            return Err(LoadError::Invalid(format!(
+                "face {} references vertex {}, but there are only {} vertices",
+                face_index,
+                index,
+                vertices.len()
+            )));
+        }
+
+        // Fan triangulation, which is exact for convex polygons
+        for k in 1..face.len().saturating_sub(1) {
+            let corners = [
+                &vertices[face[0]],
+                &vertices[face[k]],
+                &vertices[face[k + 1]],
+            ];
+
+            mesh.push(Geometry::Triangle(TriangleGeometry {
+                a: corners[0].position,
+                b: corners[1].position,
+                c: corners[2].position,
+                normals: has_normals.then(|| corners.map(|v| v.normal.unwrap())),
+                uvs: None,
+            }));
+            if has_colors {
+                vertex_colors.push(corners.map(|v| v.color.unwrap()));
+            }
+        }
+    }
+
+    Ok(PlyMesh {
+        mesh,
+        vertex_colors: (has_colors && !vertices.is_empty()).then_some(vertex_colors),
+    })
+}
+
+/// Returns the format, elements, byte offset of the body, and number of lines
+/// in the header
+fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, usize, usize), LoadError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut offset = 0;
+    let mut line_number = 0;
+
+    loop {
+        let Some(length) = bytes[offset..].iter().position(|b| *b == b'\n') else {
+            return Err(LoadError::Invalid("header has no end_header".to_string()));
+        };
+        let line = String::from_utf8_lossy(&bytes[offset..offset + length]);
+        offset += length + 1;
+        line_number += 1;
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        if line_number == 1 {
+            if tokens != ["ply"] {
+                return Err(LoadError::parse(1, "file doesn't begin with `ply`"));
+            }
+            continue;
+        }
+
+        match tokens[..] {
+            ["format", name, _version] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => {
+                        return Err(LoadError::parse(
+                            line_number,
+                            format!("unknown format `{}`", name),
+                        ))
+                    }
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| {
+                    LoadError::parse(line_number, format!("invalid count `{}`", count))
+                })?,
+                properties: vec![],
+            }),
+            ["property", "list", count_type, item_type, name] => {
+                let property = Property::List(
+                    name.to_string(),
+                    parse_scalar(count_type, line_number)?,
+                    parse_scalar(item_type, line_number)?,
+                );
+                current_element(&mut elements, line_number)?
+                    .properties
+                    .push(property);
+            }
+            ["property", scalar_type, name] => {
+                let property =
+                    Property::Scalar(name.to_string(), parse_scalar(scalar_type, line_number)?);
+                current_element(&mut elements, line_number)?
+                    .properties
+                    .push(property);
+            }
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => {
+                return Err(LoadError::parse(
+                    line_number,
+                    format!("invalid header line `{}`", line.trim()),
+                ))
+            }
+        }
+    }
+
+    let format = format.ok_or(LoadError::Invalid("header has no format".to_string()))?;
+
+    Ok((format, elements, offset, line_number))
+}
+
+fn current_element(
+    elements: &mut [Element],
+    line_number: usize,
+) -> Result<&mut Element, LoadError> {
+    elements
+        .last_mut()
+        .ok_or(LoadError::parse(line_number, "property before any element"))
+}
+
+fn parse_scalar(name: &str, line_number: usize) -> Result<Scalar, LoadError> {
+    Ok(match name {
+        "char" | "int8" => Scalar::Int8,
+        "uchar" | "uint8" => Scalar::UInt8,
+        "short" | "int16" => Scalar::Int16,
+        "ushort" | "uint16" => Scalar::UInt16,
+        "int" | "int32" => Scalar::Int32,
+        "uint" | "uint32" => Scalar::UInt32,
+        "float" | "float32" => Scalar::Float32,
+        "double" | "float64" => Scalar::Float64,
+        _ => {
+            return Err(LoadError::parse(
+                line_number,
+                format!("unknown property type `{}`", name),
+            ))
+        }
+    })
+}
+
+fn read_vertex(body: &mut Body, element: &Element) -> Result<Vertex, LoadError> {
+    let mut position = [None; 3];
+    let mut normal = [None; 3];
+    let mut color = [None; 3];
+    let mut is_integer_color = false;
+
+    for property in &element.properties {
+        let Property::Scalar(name, scalar) = property else {
+            skip_property(body, property)?;
+            continue;
+        };
+        let value = body.read(*scalar)?;
+
+        // Integer colours are sRGB encoded over the full range of their type,
+        // while float colours are taken to be linear already
+        let mut channel = || match scalar.max_value() {
+            Some(max_value) => {
+                is_integer_color = true;
+                (value / max_value) as f32
+            }
+            None => value as f32,
+        };
+
+        match name.as_str() {
+            "x" => position[0] = Some(value as f32),
+            "y" => position[1] = Some(value as f32),
+            "z" => position[2] = Some(value as f32),
+            "nx" => normal[0] = Some(value as f32),
+            "ny" => normal[1] = Some(value as f32),
+            "nz" => normal[2] = Some(value as f32),
+            "red" | "r" => color[0] = Some(channel()),
+            "green" | "g" => color[1] = Some(channel()),
+            "blue" | "b" => color[2] = Some(channel()),
+            _ => {}
+        }
+    }
+
+    let [Some(x), Some(y), Some(z)] = position else {
+        return Err(LoadError::Invalid(
+            "vertex element requires x, y, and z properties".to_string(),
+        ));
+    };
+
+    Ok(Vertex {
+        position: Vector3::new(x, y, z),
+        normal: match normal {
+            [Some(x), Some(y), Some(z)] => Some(Vector3::new(x, y, z).normalize()),
+            _ => None,
+        },
+        color: match color {
+            [Some(r), Some(g), Some(b)] if is_integer_color => Some(Color::from_srgb(r, g, b)),
+            [Some(r), Some(g), Some(b)] => Some(Color::new(r, g, b)),
+            _ => None,
+        },
+    })
+}
+
+fn read_face(body: &mut Body, element: &Element) -> Result<Vec<usize>, LoadError> {
+    let mut indices = None;
+
+    for property in &element.properties {
+        match property {
+            Property::List(name, count_type, item_type)
+                if name == "vertex_indices" || name == "vertex_index" =>
+            {
+                let length = read_list_length(body, *count_type)?;
+                let face = (0..length)
+                    .map(|_| match body.read(*item_type)? {
+                        index if index < 0.0 => Err(body.error("negative vertex index")),
+                        index => Ok(index as usize),
+                    })
+                    .collect::<Result<_, _>>()?;
+                indices = Some(face);
+            }
+            _ => skip_property(body, property)?,
+        }
+    }
+
+    indices.ok_or(LoadError::Invalid(
+        "face element requires a vertex_indices list".to_string(),
+    ))
+}
+
+fn read_list_length(body: &mut Body, count_type: Scalar) -> Result<usize, LoadError> {
+    let length = body.read(count_type)?;
+    if length < 0.0 {
+        return Err(body.error("negative list length"));
+    }
+
+    Ok(length as usize)
+}
+
+/// Reads past a property whose values aren't needed
+fn skip_property(body: &mut Body, property: &Property) -> Result<(), LoadError> {
+    match property {
+        Property::Scalar(_, scalar) => {
+            body.read(*scalar)?;
+        }
+        Property::List(_, count_type, item_type) => {
+            for _ in 0..read_list_length(body, *count_type)? {
+                body.read(*item_type)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Values are read one at a time straight from the file, rather than being
+/// tokenized or copied up front
+enum Body<'a> {
+    Ascii {
+        bytes: &'a [u8],
+        offset: usize,
+        /// Line that `offset` is on, i.e., the line of the last value read
+        line_number: usize,
+    },
+    Binary {
+        bytes: &'a [u8],
+        offset: usize,
+        big_endian: bool,
+    },
+}
+
+impl Body<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, LoadError> {
+        match self {
+            Body::Ascii {
+                bytes,
+                offset,
+                line_number,
+            } => {
+                while let Some(byte) = bytes.get(*offset).filter(|b| b.is_ascii_whitespace()) {
+                    if *byte == b'\n' {
+                        *line_number += 1;
+                    }
+                    *offset += 1;
+                }
+
+                let start = *offset;
+                while bytes.get(*offset).is_some_and(|b| !b.is_ascii_whitespace()) {
+                    *offset += 1;
+                }
+                let token = &bytes[start..*offset];
+
+                if token.is_empty() {
+                    return Err(LoadError::Invalid("unexpected end of file".to_string()));
+                }
+
+                std::str::from_utf8(token)
+                    .ok()
+                    .and_then(|token| token.parse::<f64>().ok())
+                    .ok_or_else(|| {
+                        LoadError::parse(
+                            *line_number,
+                            format!("invalid number `{}`", String::from_utf8_lossy(token)),
+                        )
+                    })
+            }
+            Body::Binary {
+                bytes,
+                offset,
+                big_endian,
+            } => {
+                let big_endian = *big_endian;
+
+                Ok(match scalar {
+                    Scalar::Int8 => i8::from_be_bytes(take(bytes, offset, big_endian)?) as f64,
+                    Scalar::UInt8 => u8::from_be_bytes(take(bytes, offset, big_endian)?) as f64,
+                    Scalar::Int16 => i16::from_be_bytes(take(bytes, offset, big_endian)?) as f64,
+                    Scalar::UInt16 => u16::from_be_bytes(take(bytes, offset, big_endian)?) as f64,
+                    Scalar::Int32 => i32::from_be_bytes(take(bytes, offset, big_endian)?) as f64,
+                    Scalar::UInt32 => u32::from_be_bytes(take(bytes, offset, big_endian)?) as f64,
+                    Scalar::Float32 => f32::from_be_bytes(take(bytes, offset, big_endian)?) as f64,
+                    Scalar::Float64 => f64::from_be_bytes(take(bytes, offset, big_endian)?),
+                })
+            }
+        }
+    }
+
+    fn error(&self, message: &str) -> LoadError {
+        match self {
+            Body::Ascii { line_number, .. } => LoadError::parse(*line_number, message),
+            Body::Binary { offset, .. } => {
+                LoadError::Invalid(format!("{} at byte {} of body", message, offset))
+            }
+        }
+    }
+}
+
+/// Takes the next `N` bytes of a binary body, in big endian order, so that
+/// every type is decoded the same way
+fn take<const N: usize>(
+    bytes: &[u8],
+    offset: &mut usize,
+    big_endian: bool,
+) -> Result<[u8; N], LoadError> {
+    let Some(raw) = bytes.get(*offset..*offset + N) else {
+        return Err(LoadError::Invalid("unexpected end of file".to_string()));
+    };
+    *offset += N;
+
+    let mut raw: [u8; N] = raw.try_into().unwrap();
+    if !big_endian {
+        raw.reverse();
+    }
+
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+
+    /// Writes the vertices with integer colours of `color_type`, all channels
+    /// set to `color`, and a single face
+    fn ply(format: Format, color_type: &str, color: u32, face: &[i32]) -> Vec<u8> {
+        let format_name = match format {
+            Format::Ascii => "ascii",
+            Format::BinaryLittleEndian => "binary_little_endian",
+            Format::BinaryBigEndian => "binary_big_endian",
+        };
+        let mut bytes = format!(
+            "ply\nformat {} 1.0\ncomment written by the tests\nelement vertex {}\n\
+             property float x\nproperty float y\nproperty float z\n\
+             property {t} red\nproperty {t} green\nproperty {t} blue\n\
+             element face 1\nproperty list uchar int vertex_indices\nend_header\n",
+            format_name,
+            SQUARE.len(),
+            t = color_type,
+        )
+        .into_bytes();
+
+        let number = |bytes: &mut Vec<u8>, be: &[u8], le: &[u8]| match format {
+            Format::BinaryBigEndian => bytes.extend(be),
+            _ => bytes.extend(le),
+        };
+
+        match format {
+            Format::Ascii => {
+                for [x, y, z] in SQUARE {
+                    bytes.extend(format!("{} {} {} {c} {c} {c}\n", x, y, z, c = color).bytes());
+                }
+                let indices: Vec<String> = face.iter().map(i32::to_string).collect();
+                bytes.extend(format!("{} {}\n", face.len(), indices.join(" ")).bytes());
+            }
+            _ => {
+                for position in SQUARE {
+                    for x in position {
+                        number(&mut bytes, &x.to_be_bytes(), &x.to_le_bytes());
+                    }
+                    for _ in 0..3 {
+                        match color_type {
+                            "uchar" => bytes.push(color as u8),
+                            "ushort" => {
+                                let c = color as u16;
+                                number(&mut bytes, &c.to_be_bytes(), &c.to_le_bytes());
+                            }
+                            _ => number(&mut bytes, &color.to_be_bytes(), &color.to_le_bytes()),
+                        }
+                    }
+                }
+                bytes.push(face.len() as u8);
+                for index in face {
+                    number(&mut bytes, &index.to_be_bytes(), &index.to_le_bytes());
+                }
+            }
+        }
+
+        bytes
+    }
+
+    fn positions(mesh: &PlyMesh) -> Vec<[Vector3<f32>; 3]> {
+        mesh.mesh
+            .iter()
+            .map(|geometry| match geometry {
+                Geometry::Triangle(triangle) => [triangle.a, triangle.b, triangle.c],
+                _ => panic!("expected a triangle"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        let corner = |k: usize| Vector3::from(SQUARE[k]);
+
+        for format in [
+            Format::Ascii,
+            Format::BinaryLittleEndian,
+            Format::BinaryBigEndian,
+        ] {
+            let mesh = parse_ply(&ply(format, "uchar", 255, &[0, 1, 2, 3])).unwrap();
+
+            // The quad is split into a fan around its first corner
+            assert_eq!(
+                positions(&mesh),
+                [
+                    [corner(0), corner(1), corner(2)],
+                    [corner(0), corner(2), corner(3)]
+                ],
+                "{:?}",
+                format
+            );
+
+            let colors = mesh.vertex_colors.unwrap();
+            assert_eq!(colors.len(), 2);
+            assert!(colors.iter().flatten().all(|color| color.red() == 1.0));
+        }
+    }
+
+    #[test]
+    fn scales_integer_colors_by_their_type() {
+        for (color_type, max_value) in [("uchar", 255), ("ushort", 65535), ("uint", u32::MAX)] {
+            for format in [
+                Format::Ascii,
+                Format::BinaryLittleEndian,
+                Format::BinaryBigEndian,
+            ] {
+                for (value, expected) in [(0, 0.0), (max_value, 1.0)] {
+                    let mesh = parse_ply(&ply(format, color_type, value, &[0, 1, 2])).unwrap();
+                    let color = mesh.vertex_colors.unwrap()[0][0];
+
+                    assert!(
+                        (color.red() - expected).abs() < 1e-6,
+                        "{} {:?} decoded {} as {}",
+                        color_type,
+                        format,
+                        value,
+                        color.red()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_face_indices() {
+        for format in [
+            Format::Ascii,
+            Format::BinaryLittleEndian,
+            Format::BinaryBigEndian,
+        ] {
+            assert!(matches!(
+                parse_ply(&ply(format, "uchar", 0, &[0, 1, 4])),
+                Err(LoadError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn reports_ascii_line_numbers() {
+        let bytes = String::from_utf8(ply(Format::Ascii, "uchar", 0, &[0, 1, 2]))
+            .unwrap()
+            .replace("1 1 0 0 0 0", "1 one 0 0 0 0");
+
+        // 13 header lines, then the third vertex
+        match parse_ply(bytes.as_bytes()) {
+            Err(LoadError::Parse { line: 16, .. }) => {}
+            result => panic!("expected a parse error on line 16, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_bodies() {
+        for format in [
+            Format::Ascii,
+            Format::BinaryLittleEndian,
+            Format::BinaryBigEndian,
+        ] {
+            let bytes = ply(format, "uchar", 0, &[0, 1, 2]);
+
+            // Cuts into the last face index
+            assert!(matches!(
+                parse_ply(&bytes[..bytes.len() - 3]),
+                Err(LoadError::Invalid(_))
+            ));
+        }
+    }
+}
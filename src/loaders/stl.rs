@@ -0,0 +1,398 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use na::Vector3;
+
+use crate::geometry::{triangle::TriangleGeometry, Geometry};
+
+use super::LoadError;
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+#[derive(Debug, Default)]
+pub struct StlOptions {
+    /// Merges vertices closer than this distance, so that triangles of the
+    /// same part share exact positions
+    pub weld_tolerance: Option<f32>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StlEncoding {
+    Ascii,
+    Binary,
+}
+
+pub fn load_stl(path: impl AsRef<Path>, options: &StlOptions) -> Result<Vec<Geometry>, LoadError> {
+    parse_stl(&fs::read(path)?, options)
+}
+
+pub fn parse_stl(bytes: &[u8], options: &StlOptions) -> Result<Vec<Geometry>, LoadError> {
+    let triangles = match detect_encoding(bytes)? {
+        StlEncoding::Ascii => parse_ascii(bytes)?,
+        StlEncoding::Binary => parse_binary(bytes)?,
+    };
+
+    let triangles = match options.weld_tolerance {
+        Some(tolerance) if !tolerance.is_finite() || tolerance <= 0.0 => {
+            return Err(LoadError::Invalid(format!(
+                "weld tolerance must be positive and finite, got {}",
+                tolerance
+            )));
+        }
+        Some(tolerance) => weld(triangles, tolerance),
+        None => triangles,
+    };
+
+    Ok(triangles
+        .into_iter()
+        .map(|[a, b, c]| {
+            Geometry::Triangle(TriangleGeometry {
+                a,
+                b,
+                c,
+                normals: None,
+                uvs: None,
+            })
+        })
+        .collect())
+}
+
+/// Binary files may also begin with "solid", since the 80 byte header is
+/// free-form, so a file is only treated as ASCII if its size doesn't match the
+/// triangle count of a binary file
+pub fn detect_encoding(bytes: &[u8]) -> Result<StlEncoding, LoadError> {
+    if let Some(count) = bytes.get(HEADER_SIZE..HEADER_SIZE + 4) {
+        let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+
+        if count
+            .checked_mul(TRIANGLE_SIZE)
+            .and_then(|size| size.checked_add(HEADER_SIZE + 4))
+            == Some(bytes.len())
+        {
+            return Ok(StlEncoding::Binary);
+        }
+    }
+
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace());
+    if start.is_some_and(|start| bytes[start..].starts_with(b"solid")) {
+        return Ok(StlEncoding::Ascii);
+    }
+
+    Err(LoadError::Invalid(
+        "file is neither ASCII STL nor binary STL with a matching triangle count".to_string(),
+    ))
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<[Vector3<f32>; 3]>, LoadError> {
+    bytes[HEADER_SIZE + 4..]
+        .chunks_exact(TRIANGLE_SIZE)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let read_vector = |offset: usize| {
+                Vector3::from_fn(|k, _| {
+                    let start = offset + 4 * k;
+                    f32::from_le_bytes(chunk[start..start + 4].try_into().unwrap())
+                })
+            };
+
+            // The stored facet normal (first 12 bytes) is ignored, since the
+            // winding order is what determines the normal of a triangle
+            let vertices = [read_vector(12), read_vector(24), read_vector(36)];
+
+            if vertices.iter().flatten().any(|x| !x.is_finite()) {
+                return Err(LoadError::Invalid(format!(
+                    "triangle {} has a non-finite vertex",
+                    index
+                )));
+            }
+
+            Ok(vertices)
+        })
+        .collect()
+}
+
+fn parse_ascii(bytes: &[u8]) -> Result<Vec<[Vector3<f32>; 3]>, LoadError> {
+    let text = std::str::from_utf8(bytes)
+        .map_err(|_| LoadError::Invalid("ASCII STL is not valid UTF-8".to_string()))?;
+
+    let mut triangles = vec![];
+    let mut vertices: Vec<Vector3<f32>> = vec![];
+    let mut in_facet = false;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.first().copied() {
+            Some("facet") => {
+                if in_facet {
+                    return Err(LoadError::parse(line_number, "facet inside facet"));
+                }
+                in_facet = true;
+                vertices.clear();
+            }
+            Some("vertex") => {
+                if !in_facet {
+                    return Err(LoadError::parse(line_number, "vertex outside facet"));
+                }
+                if tokens.len() != 4 {
+                    return Err(LoadError::parse(line_number, "vertex requires 3 values"));
+                }
+
+                let xyz = tokens[1..]
+                    .iter()
+                    .map(|token| {
+                        token.parse::<f32>().map_err(|_| {
+                            LoadError::parse(line_number, format!("invalid number `{}`", token))
+                        })
+                    })
+                    .collect::<Result<Vec<f32>, _>>()?;
+                vertices.push(Vector3::new(xyz[0], xyz[1], xyz[2]));
+            }
+            Some("endfacet") => {
+                if !in_facet {
+                    return Err(LoadError::parse(line_number, "endfacet outside facet"));
+                }
+                let [a, b, c] = vertices[..] else {
+                    return Err(LoadError::parse(
+                        line_number,
+                        format!("facet has {} vertices, expected 3", vertices.len()),
+                    ));
+                };
+                triangles.push([a, b, c]);
+                in_facet = false;
+            }
+            Some("solid" | "endsolid" | "outer" | "endloop") | None => {}
+            Some(keyword) => {
+                return Err(LoadError::parse(
+                    line_number,
+                    format!("unexpected `{}`", keyword),
+                ));
+            }
+        }
+    }
+
+    if in_facet {
+        return Err(LoadError::Invalid("unterminated facet".to_string()));
+    }
+
+    Ok(triangles)
+}
+
+/// Snaps each vertex to the first vertex found within `tolerance`, dropping any
+/// triangles that collapse as a result
+fn weld(triangles: Vec<[Vector3<f32>; 3]>, tolerance: f32) -> Vec<[Vector3<f32>; 3]> {
+    // Vertices are bucketed in a grid of tolerance sized cells, so only the
+    // neighbouring cells need to be searched for a match
+    let mut grid: HashMap<[i64; 3], Vec<Vector3<f32>>> = HashMap::new();
+    let cell = |v: &Vector3<f32>| v.map(|x| (x / tolerance).floor() as i64);
+
+    let mut snap = |vertex: Vector3<f32>| {
+        let center = cell(&vertex);
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    // Cells of vertices far out relative to the tolerance
+                    // saturate, and their neighbours past the edge don't exist
+                    let (Some(x), Some(y), Some(z)) = (
+                        center.x.checked_add(dx),
+                        center.y.checked_add(dy),
+                        center.z.checked_add(dz),
+                    ) else {
+                        continue;
+                    };
+                    let existing = grid.get(&[x, y, z]).and_then(|vertices| {
+                        vertices
+                            .iter()
+                            .find(|other| (*other - vertex).norm() <= tolerance)
+                    });
+
+                    if let Some(existing) = existing {
+                        return *existing;
+                    }
+                }
+            }
+        }
+
+        grid.entry([center.x, center.y, center.z])
+            .or_default()
+            .push(vertex);
+        vertex
+    };
+
+    triangles
+        .into_iter()
+        .map(|vertices| vertices.map(&mut snap))
+        .filter(|[a, b, c]| a != b && b != c && c != a)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: &str = "solid part
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid part
+";
+
+    fn binary(header: &[u8], triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(HEADER_SIZE, b' ');
+        bytes.extend((triangles.len() as u32).to_le_bytes());
+
+        for triangle in triangles {
+            bytes.extend([0u8; 12]);
+            for x in triangle.iter().flatten() {
+                bytes.extend(x.to_le_bytes());
+            }
+            bytes.extend([0u8; 2]);
+        }
+
+        bytes
+    }
+
+    fn vertices(geometries: &[Geometry]) -> Vec<[Vector3<f32>; 3]> {
+        geometries
+            .iter()
+            .map(|geometry| match geometry {
+                Geometry::Triangle(triangle) => [triangle.a, triangle.b, triangle.c],
+                _ => panic!("expected a triangle"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_ascii() {
+        let geometries = parse_stl(ASCII.as_bytes(), &StlOptions::default()).unwrap();
+
+        assert_eq!(
+            detect_encoding(ASCII.as_bytes()).unwrap(),
+            StlEncoding::Ascii
+        );
+        assert_eq!(
+            vertices(&geometries),
+            [[Vector3::zeros(), Vector3::x(), Vector3::y()]]
+        );
+    }
+
+    #[test]
+    fn reports_ascii_line_numbers() {
+        let text = ASCII.replace("vertex 1 0 0", "vertex 1 zero 0");
+
+        match parse_stl(text.as_bytes(), &StlOptions::default()) {
+            Err(LoadError::Parse { line: 5, .. }) => {}
+            result => panic!("expected a parse error on line 5, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn parses_binary() {
+        let bytes = binary(
+            b"binary",
+            &[[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]],
+        );
+        let geometries = parse_stl(&bytes, &StlOptions::default()).unwrap();
+
+        assert_eq!(detect_encoding(&bytes).unwrap(), StlEncoding::Binary);
+        assert_eq!(
+            vertices(&geometries),
+            [[Vector3::zeros(), Vector3::x(), Vector3::y()]]
+        );
+    }
+
+    #[test]
+    fn parses_binary_with_solid_header() {
+        let bytes = binary(
+            b"solid exported by a CAD package",
+            &[[[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]]],
+        );
+        let geometries = parse_stl(&bytes, &StlOptions::default()).unwrap();
+
+        assert_eq!(detect_encoding(&bytes).unwrap(), StlEncoding::Binary);
+        assert_eq!(geometries.len(), 1);
+    }
+
+    #[test]
+    fn rejects_non_finite_binary_vertices() {
+        let bytes = binary(
+            b"",
+            &[[[f32::NAN, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]],
+        );
+
+        assert!(matches!(
+            parse_stl(&bytes, &StlOptions::default()),
+            Err(LoadError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn welds_nearby_vertices() {
+        let bytes = binary(
+            b"",
+            &[
+                [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+                [[1.0005, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0005, 0.0]],
+            ],
+        );
+        let options = StlOptions {
+            weld_tolerance: Some(1e-3),
+        };
+        let triangles = vertices(&parse_stl(&bytes, &options).unwrap());
+
+        assert_eq!(triangles[1][0], triangles[0][1]);
+        assert_eq!(triangles[1][2], triangles[0][2]);
+    }
+
+    #[test]
+    fn drops_triangles_collapsed_by_welding() {
+        let bytes = binary(
+            b"",
+            &[
+                [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+                [[0.0, 0.0, 0.0], [0.0005, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            ],
+        );
+        let options = StlOptions {
+            weld_tolerance: Some(1e-3),
+        };
+
+        assert_eq!(parse_stl(&bytes, &options).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn welds_with_tiny_tolerances_and_large_coordinates() {
+        let bytes = binary(
+            b"",
+            &[[[0.0, 0.0, 0.0], [1e30, 0.0, 0.0], [0.0, -1e30, 0.0]]],
+        );
+
+        for tolerance in [1e-30, 1e-3] {
+            let options = StlOptions {
+                weld_tolerance: Some(tolerance),
+            };
+            assert_eq!(parse_stl(&bytes, &options).unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_weld_tolerances() {
+        for tolerance in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let options = StlOptions {
+                weld_tolerance: Some(tolerance),
+            };
+
+            assert!(matches!(
+                parse_stl(ASCII.as_bytes(), &options),
+                Err(LoadError::Invalid(_))
+            ));
+        }
+    }
+}
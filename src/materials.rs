@@ -7,4 +7,29 @@ pub struct Material {
     pub specular_color: Color,
     pub shininess: i32,
     pub reflectance: f32,
+    /// Makes the material transparent, e.g., glass or water, in which case
+    /// the Phong parameters and reflectance are unused
+    pub dielectric: Option<Dielectric>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dielectric {
+    /// Ratio of the speed of light in a vacuum to its speed in the material,
+    /// e.g., 1.33 for water or 1.5 for glass
+    pub refractive_index: f32,
+    /// Fraction of each channel absorbed per unit of distance travelled
+    /// through the material, which tints thick parts more than thin ones
+    pub absorption: Color,
+}
+
+impl Dielectric {
+    /// Fraction of each channel left after travelling `distance` through the
+    /// material
+    pub fn transmittance(&self, distance: f32) -> Color {
+        Color::new(
+            (-self.absorption.red() * distance).exp(),
+            (-self.absorption.green() * distance).exp(),
+            (-self.absorption.blue() * distance).exp(),
+        )
+    }
 }
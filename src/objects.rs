@@ -1,3 +1,5 @@
+use std::ptr;
+
 use crate::{geometry::Geometry, materials::Material};
 
 pub struct Object<'a> {
@@ -5,6 +7,18 @@ pub struct Object<'a> {
     pub material: &'a Material,
 }
 
+/// A single piece of geometry with its material, as stored in the BVH
+#[derive(Debug, Clone, Copy)]
+pub struct Primitive<'a> {
+    pub geometry: &'a Geometry,
+    pub material: &'a Material,
+    /// Index of the object the geometry belongs to
+    pub object_id: usize,
+    /// Index of the material among the distinct materials of the scene, in the
+    /// order they're first used
+    pub material_id: usize,
+}
+
 pub struct ObjectIterator<'a> {
     entries: Vec<(&'a Geometry, &'a Material)>,
     index: usize,
@@ -38,7 +52,34 @@ impl<'a> Iterator for ObjectIterator<'a> {
 }
 
 impl Object<'_> {
-    pub fn iter(&self) -> ObjectIterator {
+    pub fn iter(&self) -> ObjectIterator<'_> {
         ObjectIterator::new(self)
     }
 }
+
+/// Splits the objects into primitives, numbering materials by identity, so
+/// objects sharing a material also share its ID
+pub fn primitives<'a>(objects: &[Object<'a>]) -> Vec<Primitive<'a>> {
+    let mut materials: Vec<&Material> = vec![];
+
+    objects
+        .iter()
+        .enumerate()
+        .flat_map(|(object_id, object)| {
+            let material_id = materials
+                .iter()
+                .position(|material| ptr::eq(*material, object.material))
+                .unwrap_or_else(|| {
+                    materials.push(object.material);
+                    materials.len() - 1
+                });
+
+            object.mesh.iter().map(move |geometry| Primitive {
+                geometry,
+                material: object.material,
+                object_id,
+                material_id,
+            })
+        })
+        .collect()
+}
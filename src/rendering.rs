@@ -1,81 +1,428 @@
+pub mod aov;
+pub mod denoising;
+pub mod framebuffer;
+pub mod integrators;
 pub mod ray;
 
-use image::RgbImage;
-use na::Vector3;
+use std::{error::Error, fmt, io};
+
+use image::ImageError;
+use na::Vector2;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use rayon::{prelude::*, ThreadPoolBuildError, ThreadPoolBuilder};
 
 use crate::{
-    cameras::Camera,
-    geometry::Geometry,
+    bvh::BVH,
+    cameras::{
+        stereo::{Eye, StereoCamera},
+        Camera,
+    },
     lighting::{Color, Light},
-    materials::Material,
-    objects::Object,
+    objects::{self, Object},
 };
 
-use ray::Ray;
+use aov::Aov;
+use framebuffer::Framebuffer;
+use integrators::Integrator;
+
+#[derive(Debug)]
+pub enum RenderError {
+    InvalidDimensions {
+        width: u32,
+        height: u32,
+    },
+    ZeroAntiAliasing,
+    /// The integrator traces no rays per sample
+    ZeroSamples,
+    /// The anti-aliasing grid and integrator samples add up to more samples
+    /// per pixel than fit in a u32
+    TooManySamples {
+        anti_aliasing: u32,
+        samples: u32,
+    },
+    ThreadPool(ThreadPoolBuildError),
+    Io(io::Error),
+    /// Encoding failures other than I/O
+    Image(ImageError),
+    Exr(exr::error::Error),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::InvalidDimensions { width, height } => {
+                write!(f, "invalid image dimensions {}x{}", width, height)
+            }
+            RenderError::ZeroAntiAliasing => write!(f, "anti-aliasing must be at least 1"),
+            RenderError::ZeroSamples => write!(f, "integrator must trace at least 1 sample"),
+            RenderError::TooManySamples {
+                anti_aliasing,
+                samples,
+            } => write!(
+                f,
+                "{}x{} anti-aliasing with {} samples each is too many samples per pixel",
+                anti_aliasing, anti_aliasing, samples
+            ),
+            RenderError::ThreadPool(error) => {
+                write!(f, "failed to start render threads: {}", error)
+            }
+            RenderError::Io(error) => write!(f, "{}", error),
+            RenderError::Image(error) => write!(f, "{}", error),
+            RenderError::Exr(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for RenderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RenderError::ThreadPool(error) => Some(error),
+            RenderError::Io(error) => Some(error),
+            RenderError::Image(error) => Some(error),
+            RenderError::Exr(error) => Some(error),
+            RenderError::InvalidDimensions { .. }
+            | RenderError::ZeroAntiAliasing
+            | RenderError::ZeroSamples
+            | RenderError::TooManySamples { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for RenderError {
+    fn from(error: io::Error) -> Self {
+        RenderError::Io(error)
+    }
+}
+
+pub struct RenderSettings {
+    /// Distance shadow and reflected rays start off of a surface, so that they
+    /// don't hit it again due to floating point error
+    pub bias: f32,
+    /// Seeds the random numbers given to the integrator, so that a render can
+    /// be repeated exactly
+    pub seed: u64,
+    /// Number of threads to render with, or None to use every core
+    pub threads: Option<usize>,
+    /// Width and height in pixels of the square tiles each thread renders
+    pub tile_size: u32,
+    /// Passes to render into the framebuffer alongside the beauty image
+    pub aovs: Vec<Aov>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            bias: 1e-3,
+            seed: 0,
+            threads: None,
+            tile_size: 32,
+            aovs: vec![],
+        }
+    }
+}
 
-pub fn render(
-    objects: &Vec<Object>,
-    lights: &Vec<Light>,
+#[allow(clippy::too_many_arguments)]
+pub fn render<I: Integrator + ?Sized>(
+    objects: &[Object],
+    lights: &[Light],
     camera: &Camera,
     image_width: u32,
     image_height: u32,
     anti_aliasing: u32,
-    filename: &String,
-) {
-    // Collect entries here, so it isn't repeated for every ray
-    let entries: Vec<(&Geometry, &Material)> =
-        objects.iter().flat_map(|object| object.iter()).collect();
+    integrator: &I,
+    settings: &RenderSettings,
+) -> Result<Framebuffer, RenderError> {
+    // The supersampled image must also fit, since samples are indexed by u32
+    if image_width == 0
+        || image_height == 0
+        || image_width.checked_mul(anti_aliasing).is_none()
+        || image_height.checked_mul(anti_aliasing).is_none()
+    {
+        return Err(RenderError::InvalidDimensions {
+            width: image_width,
+            height: image_height,
+        });
+    }
+    if anti_aliasing == 0 {
+        return Err(RenderError::ZeroAntiAliasing);
+    }
+    let samples = integrator.samples();
+    if samples == 0 {
+        return Err(RenderError::ZeroSamples);
+    }
+    // Samples of a pixel are also indexed by u32
+    let Some(samples_per_pixel) = anti_aliasing
+        .checked_pow(2)
+        .and_then(|grid_samples| grid_samples.checked_mul(samples))
+    else {
+        return Err(RenderError::TooManySamples {
+            anti_aliasing,
+            samples,
+        });
+    };
+
+    // Collect primitives and build the BVH here, so it isn't repeated for every
+    // ray
+    let bvh = BVH::new(&objects::primitives(objects));
 
     let samples_wide = image_width * anti_aliasing;
     let samples_high = image_height * anti_aliasing;
-    let num_samples = samples_wide * samples_high;
 
-    let sample_size = camera.focal_plane_height() / (samples_high as f32);
-    let sample_z = -camera.focal_length;
-    let origin = Vector3::new(0.0, 0.0, 0.0);
+    let sample_size = camera.view_height() / (samples_high as f32);
 
-    let samples: Vec<Color> = (0..num_samples)
-        .map(|k| {
-            let [i, j] = [k / samples_wide, k % samples_wide];
+    // i and j are the row and column of the sample in the supersampled image,
+    // and the offset moves the ray within the sample, in fractions of its size
+    let sample_ray = |i: u32, j: u32, [offset_x, offset_y]: [f32; 2], rng: &mut Pcg32| {
+        let sample_x = (j as f32 + offset_x - (samples_wide as f32 - 1.0) / 2.0) * sample_size;
+        let sample_y = -(i as f32 + offset_y - (samples_high as f32 - 1.0) / 2.0) * sample_size;
 
-            let sample_x = (j as f32 - (samples_wide as f32 - 1.0) / 2.0) * sample_size;
-            let sample_y = -(i as f32 - (samples_high as f32 - 1.0) / 2.0) * sample_size;
+        camera.ray(&Vector2::new(sample_x, sample_y), rng)
+    };
 
-            let sample_center = Vector3::new(sample_x, sample_y, sample_z);
+    // Every pixel only depends on its own samples, and gets its own random
+    // number generator, so the result is the same no matter how tiles are
+    // split between threads
+    let pixel_colors = |x: u32, y: u32| {
+        // The seed fills the upper bits, so that no two seeds share a generator
+        // for any pixel
+        let pixel_index = y as u64 * image_width as u64 + x as u64;
+        let mut rng = Pcg32::seed_from_u64(settings.seed.rotate_left(32) ^ pixel_index);
 
-            let ray = Ray::new(origin, sample_center);
+        let mut color = Color::new_black();
+        let mut aov_colors = vec![Color::new_black(); settings.aovs.len()];
 
-            ray.color(&entries, lights)
-        })
-        .collect();
+        for k in 0..samples_per_pixel {
+            let l = k / samples;
+            let [m, n] = [l / anti_aliasing, l % anti_aliasing];
 
-    let pixels = downsample(&samples, anti_aliasing, image_width);
+            // A single ray goes through the middle of the sample
+            let offset = match samples {
+                1 => [0.0, 0.0],
+                _ => [rng.gen::<f32>() - 0.5, rng.gen::<f32>() - 0.5],
+            };
+            // Samples the projection doesn't cover, e.g., outside a fisheye
+            // circle, stay black
+            let Some(ray) = sample_ray(
+                y * anti_aliasing + m,
+                x * anti_aliasing + n,
+                offset,
+                &mut rng,
+            ) else {
+                continue;
+            };
 
-    write_image(&pixels, image_width, image_height, filename);
-}
+            let radiance = integrator.radiance(&ray, &bvh, lights, settings, &mut rng);
+            color = color + radiance.total() / (samples_per_pixel as f32);
 
-fn write_image(pixels: &Vec<Color>, image_width: u32, image_height: u32, filename: &String) {
-    let buffer: Vec<u8> = pixels.iter().flat_map(|p| p.as_8_bit_array()).collect();
+            if settings.aovs.is_empty() {
+                continue;
+            }
 
-    RgbImage::from_raw(image_width, image_height, buffer).map(|image| image.save(filename));
-}
+            let hit = bvh.intersection(&ray, 0.0, f32::INFINITY);
+            for (aov, aov_color) in settings.aovs.iter().zip(&mut aov_colors) {
+                if aov.is_filtered() {
+                    *aov_color = *aov_color
+                        + aov.sample(hit.as_ref(), &radiance) / (samples_per_pixel as f32);
+                } else if k == 0 {
+                    *aov_color = aov.sample(hit.as_ref(), &radiance);
+                }
+            }
+        }
 
-fn downsample(samples: &Vec<Color>, amount: u32, image_width: u32) -> Vec<Color> {
-    let samples_per_pixel = amount.pow(2);
+        (color, aov_colors)
+    };
 
-    let num_pixels = (samples.len() as u32) / samples_per_pixel;
+    let tiles = tiles(image_width, image_height, settings.tile_size);
 
-    (0..num_pixels)
-        .map(|k| {
-            let [i, j] = [amount * (k / image_width), amount * (k % image_width)];
+    // A thread count of 0 lets rayon use every core
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(settings.threads.unwrap_or(0))
+        .build()
+        .map_err(RenderError::ThreadPool)?;
 
-            (0..samples_per_pixel).fold(Color::new_black(), |color, l| {
-                let [m, n] = [l / amount, l % amount];
+    let tile_pixels: Vec<Vec<(Color, Vec<Color>)>> = pool.install(|| {
+        tiles
+            .par_iter()
+            .map(|tile| {
+                (tile.y..tile.y + tile.height)
+                    .flat_map(|y| (tile.x..tile.x + tile.width).map(move |x| (x, y)))
+                    .map(|(x, y)| pixel_colors(x, y))
+                    .collect()
+            })
+            .collect()
+    });
 
-                let sample_index = ((i + m) * amount * image_width + (j + n)) as usize;
+    let mut framebuffer = Framebuffer::with_aovs(image_width, image_height, &settings.aovs);
+    for (tile, colors) in tiles.iter().zip(tile_pixels) {
+        for (k, (color, aov_colors)) in colors.into_iter().enumerate() {
+            let [x, y] = [
+                tile.x + k as u32 % tile.width,
+                tile.y + k as u32 / tile.width,
+            ];
 
-                color + samples[sample_index] / (samples_per_pixel as f32)
-            })
+            framebuffer.set_pixel(x, y, color);
+            for (aov, aov_color) in settings.aovs.iter().zip(aov_colors) {
+                framebuffer.set_aov_pixel(*aov, x, y, aov_color);
+            }
+        }
+    }
+
+    Ok(framebuffer)
+}
+
+/// Renders both eyes of `rig` with the same settings, left eye first, e.g., to
+/// save with a `StereoLayout`
+#[allow(clippy::too_many_arguments)]
+pub fn render_stereo<I: Integrator + ?Sized>(
+    objects: &[Object],
+    lights: &[Light],
+    rig: &StereoCamera,
+    image_width: u32,
+    image_height: u32,
+    anti_aliasing: u32,
+    integrator: &I,
+    settings: &RenderSettings,
+) -> Result<[Framebuffer; 2], RenderError> {
+    let render_eye = |eye| {
+        render(
+            objects,
+            lights,
+            &rig.eye(eye),
+            image_width,
+            image_height,
+            anti_aliasing,
+            integrator,
+            settings,
+        )
+    };
+
+    Ok([render_eye(Eye::Left)?, render_eye(Eye::Right)?])
+}
+
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Splits the image into tiles, which are smaller at the right and bottom
+/// edges when the image size isn't a multiple of the tile size
+fn tiles(image_width: u32, image_height: u32, tile_size: u32) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+
+    (0..image_height)
+        .step_by(tile_size as usize)
+        .flat_map(|y| {
+            (0..image_width)
+                .step_by(tile_size as usize)
+                .map(move |x| Tile {
+                    x,
+                    y,
+                    width: tile_size.min(image_width - x),
+                    height: tile_size.min(image_height - y),
+                })
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        geometry::{aa_box::AABoxGeometry, sphere::SphereGeometry, Geometry},
+        materials::Material,
+    };
+    use integrators::{path_tracing::PathTracingIntegrator, phong::PhongIntegrator};
+    use na::Vector3;
+
+    #[test]
+    fn rejects_too_many_samples_per_pixel() {
+        let camera = Camera::default();
+        let settings = RenderSettings::default();
+        let path_tracer = PathTracingIntegrator {
+            samples: u32::MAX,
+            ..PathTracingIntegrator::default()
+        };
+
+        let results = [
+            render(&[], &[], &camera, 1, 1, 70000, &PhongIntegrator, &settings),
+            render(&[], &[], &camera, 1, 1, 2, &path_tracer, &settings),
+        ];
+
+        for result in results {
+            assert!(
+                matches!(result, Err(RenderError::TooManySamples { .. })),
+                "{:?}",
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn renders_the_same_with_any_number_of_threads() {
+        let material = Material {
+            ambient_color: Color::new_black(),
+            diffuse_color: Color::new(0.8, 0.6, 0.4),
+            specular_color: Color::new(0.1, 0.1, 0.1),
+            shininess: 20,
+            reflectance: 0.2,
+            dielectric: None,
+        };
+        let mesh = vec![
+            Geometry::Sphere(SphereGeometry {
+                center: Vector3::new(0.0, 0.0, -4.0),
+                radius: 1.0,
+            }),
+            Geometry::AABox(AABoxGeometry {
+                min: Vector3::new(-5.0, -2.0, -10.0),
+                max: Vector3::new(5.0, -1.0, 0.0),
+            }),
+        ];
+        let objects = [Object {
+            mesh: &mesh,
+            material: &material,
+        }];
+        let lights = [Light {
+            position: Vector3::new(2.0, 3.0, -1.0),
+            color: Color::new(1.0, 1.0, 1.0),
+        }];
+        let path_tracer = PathTracingIntegrator {
+            samples: 4,
+            max_bounces: 3,
+        };
+
+        // An odd tile size leaves partial tiles at the edges
+        let render_with = |threads| {
+            let settings = RenderSettings {
+                threads,
+                tile_size: 7,
+                ..RenderSettings::default()
+            };
+            let framebuffer = render(
+                &objects,
+                &lights,
+                &Camera::default(),
+                20,
+                15,
+                2,
+                &path_tracer,
+                &settings,
+            )
+            .unwrap();
+
+            framebuffer
+                .pixels()
+                .iter()
+                .map(|color| [color.red(), color.green(), color.blue()].map(f32::to_bits))
+                .collect::<Vec<_>>()
+        };
+
+        // Several threads are asked for explicitly too, since None may also
+        // give a single one
+        let single_threaded = render_with(Some(1));
+        assert_eq!(single_threaded, render_with(None));
+        assert_eq!(single_threaded, render_with(Some(3)));
+    }
+}
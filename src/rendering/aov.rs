@@ -0,0 +1,80 @@
+use crate::{geometry::Hit, lighting::Color, objects::Primitive};
+
+use super::integrators::Radiance;
+
+/// Arbitrary output variables, i.e., extra passes recorded alongside the
+/// beauty image. Every pass is stored as a color, with single values repeated
+/// in all three channels, and is black where the camera ray hits nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    /// Distance along the camera ray to the first hit
+    Depth,
+    /// World-space shading normal of the first hit, facing the camera
+    Normal,
+    /// Fraction of light the first hit reflects, without any lighting
+    Albedo,
+    /// Index of the object hit, plus 1 so that 0 means nothing was hit
+    ObjectId,
+    /// ID of the material hit, plus 1 so that 0 means nothing was hit
+    MaterialId,
+    /// World-space position of the first hit
+    Position,
+    /// Light reaching the first hit straight from the lights
+    Direct,
+    /// All other light, e.g., through reflections, refractions and bounces
+    Indirect,
+}
+
+impl Aov {
+    /// Name of the pass, e.g., for OpenEXR layers
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Position => "position",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+        }
+    }
+
+    /// Whether the samples of a pixel are averaged. IDs come from a single
+    /// sample instead, so they aren't blended into other IDs at edges.
+    pub fn is_filtered(&self) -> bool {
+        !matches!(self, Aov::ObjectId | Aov::MaterialId)
+    }
+
+    /// Value of the pass for a single sample
+    pub(super) fn sample(&self, hit: Option<&(Hit, Primitive)>, radiance: &Radiance) -> Color {
+        let scalar = |value: f32| Color::new(value, value, value);
+
+        match (self, hit) {
+            (Aov::Direct, _) => radiance.direct,
+            (Aov::Indirect, _) => radiance.indirect,
+            (_, None) => Color::new_black(),
+            (Aov::Depth, Some((hit, _))) => scalar(hit.t),
+            (Aov::Normal, Some((hit, _))) => {
+                let normal = hit.shading_normal;
+                Color::new(normal.x, normal.y, normal.z)
+            }
+            (Aov::Albedo, Some((_, primitive))) => {
+                let material = primitive.material;
+
+                // Glass and mirrors pass on all of the light, tinted only by
+                // what's behind or in front of them
+                match material.dielectric {
+                    Some(_) => scalar(1.0),
+                    None => {
+                        material.diffuse_color * (1.0 - material.reflectance)
+                            + scalar(material.reflectance)
+                    }
+                }
+            }
+            (Aov::ObjectId, Some((_, primitive))) => scalar((primitive.object_id + 1) as f32),
+            (Aov::MaterialId, Some((_, primitive))) => scalar((primitive.material_id + 1) as f32),
+            (Aov::Position, Some((hit, _))) => Color::new(hit.point.x, hit.point.y, hit.point.z),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use rayon::prelude::*;
+
+use crate::lighting::Color;
+
+use super::{aov::Aov, framebuffer::Framebuffer};
+
+/// Weights of the B3 spline the filter is built from, for offsets of -2 to 2
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Edge-avoiding À-Trous wavelet filter (Dammertz et al., 2010), which blurs
+/// noise away over successively wider, sparser kernels, while the albedo,
+/// normal and depth AOVs of the framebuffer stop it from blurring across edges.
+///
+/// Each guide is only used if the framebuffer was rendered with its AOV. The
+/// sigmas set how different two pixels can be before they stop being
+/// averaged, so smaller values keep more detail, and more noise.
+#[derive(Debug)]
+pub struct ATrousDenoiser {
+    /// Each iteration doubles the width of the filter, which starts at 5 pixels
+    pub iterations: u32,
+    /// Halved every iteration, since the noise shrinks as it's filtered
+    pub color_sigma: f32,
+    pub albedo_sigma: f32,
+    pub normal_sigma: f32,
+    /// Relative to the depth of the pixel being filtered
+    pub depth_sigma: f32,
+}
+
+impl Default for ATrousDenoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            color_sigma: 0.5,
+            albedo_sigma: 0.1,
+            normal_sigma: 0.2,
+            depth_sigma: 0.05,
+        }
+    }
+}
+
+impl ATrousDenoiser {
+    /// Replaces the beauty image of the framebuffer, leaving its AOVs as they
+    /// were rendered
+    pub fn denoise(&self, framebuffer: &mut Framebuffer) {
+        let [width, height] = [framebuffer.width(), framebuffer.height()].map(|d| d as usize);
+        let albedo = framebuffer.aov_pixels(Aov::Albedo);
+        let normal = framebuffer.aov_pixels(Aov::Normal);
+        let depth = framebuffer.aov_pixels(Aov::Depth);
+
+        let mut color = framebuffer.pixels().to_vec();
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            let color_sigma = self.color_sigma / (step as f32);
+            let input = color;
+
+            color = (0..width * height)
+                .into_par_iter()
+                .map(|p| {
+                    let [x, y] = [p % width, p / width];
+                    let mut sum = Color::new_black();
+                    let mut weight_sum = 0.0;
+
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        for (i, kx) in KERNEL.iter().enumerate() {
+                            // Offsets outside the image are skipped, and the
+                            // remaining weights renormalized
+                            let (Some(qx), Some(qy)) = (
+                                (x + step * i).checked_sub(2 * step),
+                                (y + step * j).checked_sub(2 * step),
+                            ) else {
+                                continue;
+                            };
+                            if qx >= width || qy >= height {
+                                continue;
+                            }
+                            let q = qy * width + qx;
+
+                            let mut weight = kx * ky * similarity(&input, p, q, color_sigma);
+                            if let Some(albedo) = albedo {
+                                weight *= similarity(albedo, p, q, self.albedo_sigma);
+                            }
+                            if let Some(normal) = normal {
+                                weight *= similarity(normal, p, q, self.normal_sigma);
+                            }
+                            if let Some(depth) = depth {
+                                let reference = depth[p].red().max(f32::EPSILON);
+                                weight *= similarity(depth, p, q, self.depth_sigma * reference);
+                            }
+
+                            sum = sum + input[q] * weight;
+                            weight_sum += weight;
+                        }
+                    }
+
+                    // The center pixel always has a positive weight, unless a
+                    // sigma is 0, in which case the pixel is left alone
+                    if weight_sum > 0.0 {
+                        sum / weight_sum
+                    } else {
+                        input[p]
+                    }
+                })
+                .collect();
+        }
+
+        framebuffer.pixels_mut().copy_from_slice(&color);
+    }
+}
+
+/// Gaussian falloff of the distance between pixels p and q of a buffer
+fn similarity(pixels: &[Color], p: usize, q: usize, sigma: f32) -> f32 {
+    let [a, b] = [pixels[p], pixels[q]];
+    let distance_squared = (a.red() - b.red()).powi(2)
+        + (a.green() - b.green()).powi(2)
+        + (a.blue() - b.blue()).powi(2);
+
+    (-distance_squared / sigma.powi(2)).exp()
+}
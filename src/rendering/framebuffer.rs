@@ -0,0 +1,355 @@
+pub mod openexr;
+pub mod pfm;
+pub mod radiance;
+
+use std::path::{Path, PathBuf};
+
+use image::{ImageError, Rgb, RgbImage};
+
+use crate::lighting::{Color, ToneMapping};
+
+use super::{aov::Aov, RenderError};
+
+use openexr::{write_exr, ExrPrecision};
+use pfm::write_pfm;
+use radiance::write_hdr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// 8 bits per channel, tone mapped and sRGB encoded, in whichever format
+    /// the image crate matches to the extension, e.g., PNG or JPEG
+    Image,
+    OpenExr(ExrPrecision),
+    RadianceHdr,
+    Pfm,
+}
+
+impl OutputFormat {
+    /// Picks a float format for .exr, .hdr and .pfm files, using half precision
+    /// for OpenEXR, and `Image` for anything else
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("exr") => OutputFormat::OpenExr(ExrPrecision::default()),
+            Some("hdr") => OutputFormat::RadianceHdr,
+            Some("pfm") => OutputFormat::Pfm,
+            _ => OutputFormat::Image,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OutputSettings {
+    /// Format to encode in, or None to pick one from the file extension
+    pub format: Option<OutputFormat>,
+    /// Applied only when encoding 8 bit images, so that the framebuffer stays
+    /// linear. Float formats store the framebuffer as is, for compositing.
+    pub tone_mapping: ToneMapping,
+    /// Brightness adjustment in stops applied before tone mapping
+    pub exposure: f32,
+}
+
+/// How the left and right eyes of a stereo render are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoLayout {
+    /// Left eye on the left, in one image twice as wide
+    SideBySide,
+    /// Left eye on top, in one image twice as tall
+    OverUnder,
+    /// Each eye in a file of its own, named after the path with "_left" or
+    /// "_right" added to the file stem
+    Separate,
+}
+
+impl StereoLayout {
+    pub fn save(
+        &self,
+        [left, right]: &[Framebuffer; 2],
+        path: impl AsRef<Path>,
+        settings: &OutputSettings,
+    ) -> Result<(), RenderError> {
+        match self {
+            StereoLayout::SideBySide => left.stack(right, false)?.save(path, settings),
+            StereoLayout::OverUnder => left.stack(right, true)?.save(path, settings),
+            StereoLayout::Separate => {
+                left.save(eye_path(path.as_ref(), "left"), settings)?;
+                right.save(eye_path(path.as_ref(), "right"), settings)
+            }
+        }
+    }
+}
+
+/// Linear, unclamped colors of a rendered image, in rows from the top left,
+/// along with any AOVs rendered with it
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+    aovs: Vec<(Aov, Vec<Color>)>,
+}
+
+impl Framebuffer {
+    /// Creates a black framebuffer
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::with_aovs(width, height, &[])
+    }
+
+    /// Creates a black framebuffer with black AOVs, ignoring repeats
+    pub fn with_aovs(width: u32, height: u32, aovs: &[Aov]) -> Self {
+        let black = vec![Color::new_black(); width as usize * height as usize];
+
+        Self {
+            width,
+            height,
+            pixels: black.clone(),
+            aovs: aovs
+                .iter()
+                .enumerate()
+                .filter(|(k, aov)| !aovs[..*k].contains(aov))
+                .map(|(_, aov)| (*aov, black.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        let index = self.index(x, y);
+        self.pixels[index] = color;
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+
+    pub fn aovs(&self) -> impl Iterator<Item = Aov> + '_ {
+        self.aovs.iter().map(|(aov, _)| *aov)
+    }
+
+    pub fn aov_pixels(&self, aov: Aov) -> Option<&[Color]> {
+        self.aovs
+            .iter()
+            .find(|(other, _)| *other == aov)
+            .map(|(_, pixels)| pixels.as_slice())
+    }
+
+    pub fn aov_pixels_mut(&mut self, aov: Aov) -> Option<&mut [Color]> {
+        self.aovs
+            .iter_mut()
+            .find(|(other, _)| *other == aov)
+            .map(|(_, pixels)| pixels.as_mut_slice())
+    }
+
+    /// Panics if the framebuffer wasn't created with `aov`
+    pub fn set_aov_pixel(&mut self, aov: Aov, x: u32, y: u32, color: Color) {
+        let index = self.index(x, y);
+        let pixels = self
+            .aov_pixels_mut(aov)
+            .unwrap_or_else(|| panic!("framebuffer has no {} AOV", aov.name()));
+
+        pixels[index] = color;
+    }
+
+    /// Copies an AOV into a framebuffer of its own, e.g., to save it to a
+    /// separate file
+    pub fn aov(&self, aov: Aov) -> Option<Framebuffer> {
+        self.aov_pixels(aov).map(|pixels| Self {
+            width: self.width,
+            height: self.height,
+            pixels: pixels.to_vec(),
+            aovs: vec![],
+        })
+    }
+
+    /// Tone maps and sRGB encodes the framebuffer into 8 bits per channel
+    pub fn to_rgb_image(&self, settings: &OutputSettings) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            Rgb(settings
+                .tone_mapping
+                .apply(self.pixel(x, y), settings.exposure)
+                .as_8_bit_array())
+        })
+    }
+
+    /// OpenEXR files store every AOV as a layer alongside a "beauty" layer,
+    /// while other formats only store the beauty image, so AOVs have to be
+    /// saved separately using `aov`
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        settings: &OutputSettings,
+    ) -> Result<(), RenderError> {
+        let format = settings
+            .format
+            .unwrap_or_else(|| OutputFormat::from_path(&path));
+
+        match format {
+            OutputFormat::Image => self.to_rgb_image(settings).save(path).map_err(image_error),
+            OutputFormat::OpenExr(precision) if self.aovs.is_empty() => {
+                write_exr(path, &[("", self)], precision)
+            }
+            OutputFormat::OpenExr(precision) => {
+                let aovs: Vec<(Aov, Framebuffer)> = self
+                    .aovs()
+                    .filter_map(|aov| self.aov(aov).map(|framebuffer| (aov, framebuffer)))
+                    .collect();
+
+                let layers: Vec<(&str, &Framebuffer)> = [("beauty", self)]
+                    .into_iter()
+                    .chain(
+                        aovs.iter()
+                            .map(|(aov, framebuffer)| (aov.name(), framebuffer)),
+                    )
+                    .collect();
+
+                write_exr(path, &layers, precision)
+            }
+            OutputFormat::RadianceHdr => write_hdr(path, self),
+            OutputFormat::Pfm => write_pfm(path, self),
+        }
+    }
+
+    /// Puts `other` to the right of the framebuffer, or below it, keeping the
+    /// AOVs they both have
+    fn stack(&self, other: &Framebuffer, below: bool) -> Result<Framebuffer, RenderError> {
+        let size = match below {
+            false => self.width.checked_mul(2).map(|width| [width, self.height]),
+            true => self
+                .height
+                .checked_mul(2)
+                .map(|height| [self.width, height]),
+        };
+        let Some([width, height]) =
+            size.filter(|_| self.width == other.width && self.height == other.height)
+        else {
+            return Err(RenderError::InvalidDimensions {
+                width: other.width,
+                height: other.height,
+            });
+        };
+
+        let aovs: Vec<Aov> = self
+            .aovs()
+            .filter(|aov| other.aov_pixels(*aov).is_some())
+            .collect();
+        let mut stacked = Self::with_aovs(width, height, &aovs);
+        let offset = match below {
+            false => [self.width, 0],
+            true => [0, self.height],
+        };
+
+        for (framebuffer, [offset_x, offset_y]) in [(self, [0, 0]), (other, offset)] {
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let [stacked_x, stacked_y] = [x + offset_x, y + offset_y];
+                    stacked.set_pixel(stacked_x, stacked_y, framebuffer.pixel(x, y));
+
+                    for aov in &aovs {
+                        if let Some(pixels) = framebuffer.aov_pixels(*aov) {
+                            let color = pixels[framebuffer.index(x, y)];
+                            stacked.set_aov_pixel(*aov, stacked_x, stacked_y, color);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(stacked)
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel ({}, {}) is outside the {}x{} framebuffer",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+fn image_error(error: ImageError) -> RenderError {
+    match error {
+        ImageError::IoError(error) => RenderError::Io(error),
+        error => RenderError::Image(error),
+    }
+}
+
+/// Adds `_{eye}` to the file stem of `path`, keeping its extension
+fn eye_path(path: &Path, eye: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    match path.extension() {
+        Some(extension) => {
+            path.with_file_name(format!("{}_{}.{}", stem, eye, extension.to_string_lossy()))
+        }
+        None => path.with_file_name(format!("{}_{}", stem, eye)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Seek, SeekFrom, Write};
+
+    use super::*;
+
+    /// Fails every write, like a full disk
+    struct FullWriter;
+
+    impl Write for FullWriter {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("no space left"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for FullWriter {
+        fn seek(&mut self, _: SeekFrom) -> io::Result<u64> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn reports_failed_writes_of_float_formats() {
+        // Small enough to fit in the write buffer, so the writes only fail
+        // once it's flushed
+        let framebuffer = Framebuffer::new(4, 4);
+
+        let results = [
+            openexr::write_exr_to(FullWriter, &[("", &framebuffer)], ExrPrecision::Half),
+            openexr::write_exr_to(FullWriter, &[("", &framebuffer)], ExrPrecision::Float),
+            radiance::write_hdr_to(FullWriter, &framebuffer),
+            pfm::write_pfm_to(FullWriter, &framebuffer),
+        ];
+
+        for result in results {
+            assert!(matches!(result, Err(RenderError::Io(_))), "{:?}", result);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::Path,
+};
+
+use exr::{
+    error::Error,
+    prelude::{
+        f16, AnyChannel, AnyChannels, Encoding, FlatSamples, Image, ImageAttributes, IntegerBounds,
+        Layer, LayerAttributes, SmallVec, Vec2, WritableImage,
+    },
+};
+
+use crate::{lighting::Color, rendering::RenderError};
+
+use super::Framebuffer;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExrPrecision {
+    /// 16 bit floats, which are precise enough for color and half the size
+    #[default]
+    Half,
+    /// 32 bit floats, e.g., for depth or positions
+    Float,
+}
+
+/// Writes each framebuffer as a layer of R, G and B channels. A single layer
+/// with an empty name is written as a plain RGB image, and otherwise every
+/// layer needs a unique name, which tools show as e.g. `diffuse.R`.
+pub fn write_exr(
+    path: impl AsRef<Path>,
+    layers: &[(&str, &Framebuffer)],
+    precision: ExrPrecision,
+) -> Result<(), RenderError> {
+    write_exr_to(File::create(path)?, layers, precision)
+}
+
+/// Buffers its writes to `file`, and flushes them before returning, since
+/// errors writing the last buffered bytes would be lost if the buffer were
+/// dropped instead
+pub(super) fn write_exr_to(
+    file: impl Write + Seek,
+    layers: &[(&str, &Framebuffer)],
+    precision: ExrPrecision,
+) -> Result<(), RenderError> {
+    let Some((_, first)) = layers.first() else {
+        return Err(RenderError::Exr(Error::Invalid(
+            "no layers to write".into(),
+        )));
+    };
+    let size = Vec2(first.width() as usize, first.height() as usize);
+
+    let layers: Vec<Layer<AnyChannels<FlatSamples>>> = layers
+        .iter()
+        .map(|(name, framebuffer)| {
+            if (framebuffer.width(), framebuffer.height()) != (first.width(), first.height()) {
+                return Err(RenderError::InvalidDimensions {
+                    width: framebuffer.width(),
+                    height: framebuffer.height(),
+                });
+            }
+
+            let attributes = match *name {
+                "" => LayerAttributes::default(),
+                name => LayerAttributes::named(name),
+            };
+
+            let channel = |channel_name: &str, component: fn(&Color) -> f32| {
+                let values = framebuffer.pixels().iter().map(component);
+                let samples = match precision {
+                    ExrPrecision::Half => FlatSamples::F16(values.map(f16::from_f32).collect()),
+                    ExrPrecision::Float => FlatSamples::F32(values.collect()),
+                };
+
+                AnyChannel::new(channel_name, samples)
+            };
+
+            Ok(Layer::new(
+                size,
+                attributes,
+                Encoding::FAST_LOSSLESS,
+                AnyChannels::sort(SmallVec::from_vec(vec![
+                    channel("R", Color::red),
+                    channel("G", Color::green),
+                    channel("B", Color::blue),
+                ])),
+            ))
+        })
+        .collect::<Result<_, RenderError>>()?;
+
+    let mut writer = BufWriter::new(file);
+    Image::from_layers(
+        ImageAttributes::new(IntegerBounds::from_dimensions(size)),
+        layers,
+    )
+    .write()
+    .to_buffered(&mut writer)
+    .map_err(exr_error)?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Keeps I/O failures apart from encoding errors, as for the other formats
+fn exr_error(error: Error) -> RenderError {
+    match error {
+        Error::Io(error) => RenderError::Io(error),
+        error => RenderError::Exr(error),
+    }
+}
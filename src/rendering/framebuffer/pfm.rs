@@ -0,0 +1,43 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::rendering::RenderError;
+
+use super::Framebuffer;
+
+/// Writes a color Portable Float Map, which stores 32 bit floats with no
+/// compression or metadata
+pub fn write_pfm(path: impl AsRef<Path>, framebuffer: &Framebuffer) -> Result<(), RenderError> {
+    write_pfm_to(File::create(path)?, framebuffer)
+}
+
+/// Buffers its writes to `file`, and flushes them before returning, so that
+/// errors writing the last buffered bytes aren't lost
+pub(super) fn write_pfm_to(file: impl Write, framebuffer: &Framebuffer) -> Result<(), RenderError> {
+    let mut writer = BufWriter::new(file);
+
+    // A negative scale marks the samples as little endian
+    write!(
+        writer,
+        "PF\n{} {}\n-1.0\n",
+        framebuffer.width(),
+        framebuffer.height()
+    )?;
+
+    // Rows are stored from the bottom up
+    for y in (0..framebuffer.height()).rev() {
+        for x in 0..framebuffer.width() {
+            let color = framebuffer.pixel(x, y);
+
+            for channel in [color.red(), color.green(), color.blue()] {
+                writer.write_all(&channel.to_le_bytes())?;
+            }
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use image::{codecs::hdr::HdrEncoder, Rgb};
+
+use crate::rendering::RenderError;
+
+use super::{image_error, Framebuffer};
+
+/// Writes a Radiance RGBE .hdr file, which shares an 8 bit exponent between
+/// channels, so it can't store negative values and they're clamped to 0
+pub fn write_hdr(path: impl AsRef<Path>, framebuffer: &Framebuffer) -> Result<(), RenderError> {
+    write_hdr_to(File::create(path)?, framebuffer)
+}
+
+/// Buffers its writes to `file`, and flushes them before returning, since
+/// the encoder doesn't, and errors writing the last buffered bytes would be
+/// lost if the buffer were dropped instead
+pub(super) fn write_hdr_to(file: impl Write, framebuffer: &Framebuffer) -> Result<(), RenderError> {
+    let mut writer = BufWriter::new(file);
+
+    let pixels: Vec<Rgb<f32>> = framebuffer
+        .pixels()
+        .iter()
+        .map(|color| Rgb([color.red(), color.green(), color.blue()].map(|c| c.max(0.0))))
+        .collect();
+
+    HdrEncoder::new(&mut writer)
+        .encode(
+            &pixels,
+            framebuffer.width() as usize,
+            framebuffer.height() as usize,
+        )
+        .map_err(image_error)?;
+
+    writer.flush()?;
+    Ok(())
+}
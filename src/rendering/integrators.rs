@@ -0,0 +1,150 @@
+pub mod ambient_occlusion;
+pub mod debug;
+pub mod path_tracing;
+pub mod phong;
+pub mod whitted;
+
+use std::f32::consts::PI;
+
+use na::Vector3;
+use rand::Rng;
+use rand_pcg::Pcg32;
+
+use crate::{
+    bvh::BVH,
+    geometry::Hit,
+    lighting::{Color, Light},
+    materials::Material,
+};
+
+use super::{ray::Ray, RenderSettings};
+
+/// Light seen along a camera ray, split for the direct and indirect AOVs
+#[derive(Debug, Clone, Copy)]
+pub struct Radiance {
+    /// Light reaching the first surface hit straight from the lights
+    pub direct: Color,
+    /// All other light, e.g., through reflections, refractions and bounces
+    pub indirect: Color,
+}
+
+impl Radiance {
+    /// For integrators that don't follow rays past the first hit
+    pub fn from_direct(direct: Color) -> Self {
+        Self {
+            direct,
+            indirect: Color::new_black(),
+        }
+    }
+
+    pub fn total(&self) -> Color {
+        self.direct + self.indirect
+    }
+}
+
+/// Decides the light seen along each camera ray. `render` averages the
+/// radiance of every sample in a pixel, so implementations only handle a
+/// single ray.
+pub trait Integrator: Sync {
+    /// `rng` is seeded for every pixel, so integrators that make random
+    /// choices still render the same image every time
+    fn radiance(
+        &self,
+        ray: &Ray,
+        bvh: &BVH,
+        lights: &[Light],
+        settings: &RenderSettings,
+        rng: &mut Pcg32,
+    ) -> Radiance;
+
+    /// Number of rays averaged for every anti-aliasing sample. When more than
+    /// one, they're spread randomly over the sample, which integrators that
+    /// make random choices need in order to converge.
+    fn samples(&self) -> u32 {
+        1
+    }
+}
+
+/// Light every light contributes through the ambient color of the material,
+/// regardless of shadows
+pub fn ambient_color(material: &Material, lights: &[Light]) -> Color {
+    lights
+        .iter()
+        .map(|light| light.color * material.ambient_color)
+        .sum()
+}
+
+/// Diffuse and specular light reaching the hit straight from the lights that
+/// aren't in shadow. The diffuse term is scaled by `diffuse_weight`, which is 1
+/// for Phong shading, or 1/π for a Lambertian surface, whose reflected light is
+/// spread over the hemisphere.
+pub fn direct_color(
+    ray: &Ray,
+    hit: &Hit,
+    material: &Material,
+    bvh: &BVH,
+    lights: &[Light],
+    settings: &RenderSettings,
+    diffuse_weight: f32,
+) -> Color {
+    let hit_point = hit.point;
+    let hit_normal = hit.shading_normal;
+
+    let shadow_origin = hit.offset_point(settings.bias);
+
+    lights
+        .iter()
+        .map(|light| {
+            let light_direction = light.direction_from(&hit_point);
+
+            let light_distance = (light.position - shadow_origin).norm();
+            let shadow_ray = Ray::new(shadow_origin, light.position - shadow_origin);
+            if bvh.is_occluded(&shadow_ray, 0.0, light_distance) {
+                return Color::new_black();
+            }
+
+            // TODO: hit_point used twice. Can we optimize?
+            // Surfaces facing away from the light get none of it, rather
+            // than negative light
+            let diffusion = light_direction.dot(&hit_normal).max(0.0) * diffuse_weight;
+            let specularity = (light_direction - ray.direction())
+                .normalize()
+                .dot(&hit_normal)
+                .max(0.0);
+
+            light.color
+                * (material.diffuse_color * diffusion
+                    + material.specular_color * specularity.powi(material.shininess))
+        })
+        .sum()
+}
+
+/// Picks a direction from the cosine-weighted hemisphere around the shading
+/// normal, or None if it would pass through the surface, which can happen
+/// where the shading and geometric normals differ
+pub fn diffuse_bounce(hit: &Hit, settings: &RenderSettings, rng: &mut Pcg32) -> Option<Ray> {
+    let normal = hit.shading_normal;
+
+    // Any vector not parallel to the normal gives a basis for the hemisphere
+    let helper = if normal.x.abs() > 0.9 {
+        Vector3::y()
+    } else {
+        Vector3::x()
+    };
+    let tangent = normal.cross(&helper).normalize();
+    let bitangent = normal.cross(&tangent);
+
+    // Uniform points on the unit disk, projected up onto the hemisphere
+    let angle = 2.0 * PI * rng.gen::<f32>();
+    let radius_squared = rng.gen::<f32>();
+    let radius = radius_squared.sqrt();
+    let direction = tangent * (radius * angle.cos())
+        + bitangent * (radius * angle.sin())
+        + normal * (1.0 - radius_squared).sqrt();
+
+    if direction.dot(&hit.geometric_normal) <= 0.0 {
+        return None;
+    }
+
+    Some(Ray::new(hit.offset_point(settings.bias), direction))
+}
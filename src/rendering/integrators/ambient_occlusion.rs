@@ -0,0 +1,59 @@
+use rand_pcg::Pcg32;
+
+use crate::{
+    bvh::BVH,
+    lighting::{Color, Light},
+    rendering::{ray::Ray, RenderSettings},
+};
+
+use super::{diffuse_bounce, Integrator, Radiance};
+
+/// Shades surfaces by how much of the hemisphere above them is open, from
+/// white where nothing is nearby to black in creases and corners. Lights and
+/// materials are ignored.
+#[derive(Debug)]
+pub struct AmbientOcclusionIntegrator {
+    /// Number of directions tested for every anti-aliasing sample
+    pub samples: u32,
+    /// Only surfaces closer than this occlude each other
+    pub distance: f32,
+}
+
+impl Default for AmbientOcclusionIntegrator {
+    fn default() -> Self {
+        Self {
+            samples: 16,
+            distance: 1.0,
+        }
+    }
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        bvh: &BVH,
+        _lights: &[Light],
+        settings: &RenderSettings,
+        rng: &mut Pcg32,
+    ) -> Radiance {
+        let Some((hit, _)) = bvh.intersection(ray, 0.0, f32::INFINITY) else {
+            return Radiance::from_direct(Color::new_black());
+        };
+
+        // Directions are cosine-weighted, so those near the horizon, which
+        // light the surface the least, also count the least
+        let color = match diffuse_bounce(&hit, settings, rng) {
+            Some(bounced) if !bvh.is_occluded(&bounced, 0.0, self.distance) => {
+                Color::new(1.0, 1.0, 1.0)
+            }
+            _ => Color::new_black(),
+        };
+
+        Radiance::from_direct(color)
+    }
+
+    fn samples(&self) -> u32 {
+        self.samples
+    }
+}
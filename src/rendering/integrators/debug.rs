@@ -0,0 +1,68 @@
+use rand_pcg::Pcg32;
+
+use crate::{
+    bvh::BVH,
+    lighting::{Color, Light},
+    rendering::{ray::Ray, RenderSettings},
+};
+
+use super::{Integrator, Radiance};
+
+/// Views of the data at the nearest hit, for inspecting scenes and loaders
+/// rather than producing final images. Misses are black.
+#[derive(Debug)]
+pub enum DebugIntegrator {
+    /// Shading normal, mapped from [-1, 1] to [0, 1] per axis
+    Normals,
+    /// Distance along the ray, from white at the camera to black at `far` and
+    /// beyond
+    Depth { far: f32 },
+    /// Texture coordinates, as red and green
+    Uv,
+    /// A distinct color for every material
+    MaterialId,
+}
+
+impl Integrator for DebugIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        bvh: &BVH,
+        _lights: &[Light],
+        _settings: &RenderSettings,
+        _rng: &mut Pcg32,
+    ) -> Radiance {
+        let Some((hit, primitive)) = bvh.intersection(ray, 0.0, f32::INFINITY) else {
+            return Radiance::from_direct(Color::new_black());
+        };
+
+        let color = match self {
+            DebugIntegrator::Normals => {
+                let normal = hit.shading_normal.map(|x| (x + 1.0) / 2.0);
+                Color::new(normal.x, normal.y, normal.z)
+            }
+            DebugIntegrator::Depth { far } => {
+                let shade = 1.0 - (hit.t / far).min(1.0);
+                Color::new(shade, shade, shade)
+            }
+            DebugIntegrator::Uv => Color::new(hit.uv.x, hit.uv.y, 0.0),
+            DebugIntegrator::MaterialId => id_color(primitive.material_id),
+        };
+
+        Radiance::from_direct(color)
+    }
+}
+
+/// Steps the hue by the golden ratio, so that consecutive IDs, which are often
+/// neighbours in the scene, get very different colors
+fn id_color(id: usize) -> Color {
+    let hue = (id as f32 * 0.618_034).fract() * 6.0;
+    let (saturation, value) = (0.65, 0.9);
+
+    let channel = |n: f32| {
+        let k = (n + hue) % 6.0;
+        value - value * saturation * k.min(4.0 - k).clamp(0.0, 1.0)
+    };
+
+    Color::new(channel(5.0), channel(3.0), channel(1.0))
+}
@@ -0,0 +1,127 @@
+use std::f32::consts::FRAC_1_PI;
+
+use rand::Rng;
+use rand_pcg::Pcg32;
+
+use crate::{
+    bvh::BVH,
+    lighting::{Color, Light},
+    rendering::{ray::Ray, RenderSettings},
+};
+
+use super::{diffuse_bounce, direct_color, Integrator, Radiance};
+
+/// Number of bounces before paths may be terminated by Russian roulette, so
+/// that short paths, which carry the most light, are never cut off
+const ROULETTE_START_BOUNCE: u32 = 3;
+
+/// Monte Carlo path tracing, which adds indirect light, such as colour bleeding
+/// between surfaces, in place of the ambient color.
+///
+/// Every sample follows a single random path, and averaging many converges to
+/// the full global illumination of the scene. At each hit, a dielectric either
+/// reflects or refracts the path, chosen by the Fresnel equations, and other
+/// materials either reflect it as a mirror, chosen by `Material::reflectance`,
+/// or scatter it diffusely. Lights are points, which paths can't hit by chance,
+/// so the light reaching every diffuse hit directly is added explicitly
+/// (next-event estimation).
+#[derive(Debug)]
+pub struct PathTracingIntegrator {
+    /// Number of paths traced for every anti-aliasing sample
+    pub samples: u32,
+    /// Maximum number of times a path can bounce, where 0 gives only direct
+    /// light
+    pub max_bounces: u32,
+}
+
+impl Default for PathTracingIntegrator {
+    fn default() -> Self {
+        Self {
+            samples: 16,
+            max_bounces: 5,
+        }
+    }
+}
+
+impl Integrator for PathTracingIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        bvh: &BVH,
+        lights: &[Light],
+        settings: &RenderSettings,
+        rng: &mut Pcg32,
+    ) -> Radiance {
+        let mut ray = ray.clone();
+        let mut radiance = Radiance::from_direct(Color::new_black());
+        // Fraction of each channel the path carries back to the camera so far
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+
+        for bounce in 0..=self.max_bounces {
+            let Some((hit, primitive)) = bvh.intersection(&ray, 0.0, f32::INFINITY) else {
+                break;
+            };
+            let material = primitive.material;
+
+            ray = if let Some(dielectric) = &material.dielectric {
+                // Hitting a back face means the path travelled through the
+                // material
+                if !hit.front_face {
+                    throughput = throughput * dielectric.transmittance(hit.t);
+                }
+
+                match ray.refract(&hit, dielectric, settings) {
+                    Some((refracted, reflectance)) if rng.gen::<f32>() >= reflectance => refracted,
+                    _ => ray.reflect(&hit, settings),
+                }
+            } else if rng.gen::<f32>() < material.reflectance {
+                ray.reflect(&hit, settings)
+            } else {
+                // A Lambertian surface reflects albedo/π, and unlike the
+                // bounce below, light sampled from the lights has no pdf to
+                // cancel the 1/π
+                let light = throughput
+                    * direct_color(&ray, &hit, material, bvh, lights, settings, FRAC_1_PI);
+
+                // Only light reaching the first hit straight from the lights is
+                // direct, even though it's sampled the same way at every hit
+                if bounce == 0 {
+                    radiance.direct = radiance.direct + light;
+                } else {
+                    radiance.indirect = radiance.indirect + light;
+                }
+
+                // Sampling proportionally to the cosine term cancels it, and
+                // the 1/π, with the pdf, leaving only the albedo
+                throughput = throughput * material.diffuse_color;
+
+                match diffuse_bounce(&hit, settings, rng) {
+                    Some(bounced) => bounced,
+                    None => break,
+                }
+            };
+
+            if bounce >= ROULETTE_START_BOUNCE {
+                // Dim paths contribute little, so they're terminated more
+                // often, and survivors are brightened to make up for the ones
+                // that weren't
+                let survival = throughput
+                    .red()
+                    .max(throughput.green())
+                    .max(throughput.blue())
+                    .min(1.0);
+
+                if rng.gen::<f32>() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+        }
+
+        radiance
+    }
+
+    fn samples(&self) -> u32 {
+        self.samples
+    }
+}
@@ -0,0 +1,34 @@
+use rand_pcg::Pcg32;
+
+use crate::{
+    bvh::BVH,
+    lighting::{Color, Light},
+    rendering::{ray::Ray, RenderSettings},
+};
+
+use super::{ambient_color, direct_color, Integrator, Radiance};
+
+/// Phong shading with shadows, but without reflections or refractions
+#[derive(Debug, Default)]
+pub struct PhongIntegrator;
+
+impl Integrator for PhongIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        bvh: &BVH,
+        lights: &[Light],
+        settings: &RenderSettings,
+        _rng: &mut Pcg32,
+    ) -> Radiance {
+        let Some((hit, primitive)) = bvh.intersection(ray, 0.0, f32::INFINITY) else {
+            return Radiance::from_direct(Color::new_black());
+        };
+        let material = primitive.material;
+
+        Radiance::from_direct(
+            ambient_color(material, lights)
+                + direct_color(ray, &hit, material, bvh, lights, settings, 1.0),
+        )
+    }
+}
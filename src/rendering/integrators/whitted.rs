@@ -0,0 +1,134 @@
+use rand_pcg::Pcg32;
+
+use crate::{
+    bvh::BVH,
+    geometry::Hit,
+    lighting::{Color, Light},
+    materials::Dielectric,
+    rendering::{ray::Ray, RenderSettings},
+};
+
+use super::{ambient_color, direct_color, Integrator, Radiance};
+
+/// Phong shading with recursive reflections and refractions
+#[derive(Debug)]
+pub struct WhittedIntegrator {
+    /// Maximum number of times a ray can be reflected or refracted
+    pub max_depth: u32,
+}
+
+impl Default for WhittedIntegrator {
+    fn default() -> Self {
+        Self { max_depth: 5 }
+    }
+}
+
+impl Integrator for WhittedIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        bvh: &BVH,
+        lights: &[Light],
+        settings: &RenderSettings,
+        _rng: &mut Pcg32,
+    ) -> Radiance {
+        self.radiance_with_depth(ray, bvh, lights, settings, 0)
+    }
+}
+
+impl WhittedIntegrator {
+    /// `depth` is the number of reflections or refractions leading up to this
+    /// ray
+    fn radiance_with_depth(
+        &self,
+        ray: &Ray,
+        bvh: &BVH,
+        lights: &[Light],
+        settings: &RenderSettings,
+        depth: u32,
+    ) -> Radiance {
+        let Some((hit, primitive)) = bvh.intersection(ray, 0.0, f32::INFINITY) else {
+            return Radiance::from_direct(Color::new_black());
+        };
+        let material = primitive.material;
+
+        if let Some(dielectric) = &material.dielectric {
+            return Radiance {
+                direct: Color::new_black(),
+                indirect: self
+                    .dielectric_color(ray, &hit, dielectric, bvh, lights, settings, depth),
+            };
+        }
+
+        let local_color = ambient_color(material, lights)
+            + direct_color(ray, &hit, material, bvh, lights, settings, 1.0);
+        let direct = local_color * (1.0 - material.reflectance);
+
+        if material.reflectance <= 0.0 || depth >= self.max_depth {
+            return Radiance::from_direct(direct);
+        }
+
+        let reflected_color = self
+            .radiance_with_depth(
+                &ray.reflect(&hit, settings),
+                bvh,
+                lights,
+                settings,
+                depth + 1,
+            )
+            .total();
+
+        Radiance {
+            direct,
+            indirect: reflected_color * material.reflectance,
+        }
+    }
+
+    /// Splits the ray into reflected and refracted rays, weighted by the
+    /// Fresnel equations. Whether the ray is entering or leaving the material
+    /// is decided by `Hit::front_face`, so triangle meshes must be closed and
+    /// wound consistently outward.
+    #[allow(clippy::too_many_arguments)]
+    fn dielectric_color(
+        &self,
+        ray: &Ray,
+        hit: &Hit,
+        dielectric: &Dielectric,
+        bvh: &BVH,
+        lights: &[Light],
+        settings: &RenderSettings,
+        depth: u32,
+    ) -> Color {
+        if depth >= self.max_depth {
+            return Color::new_black();
+        }
+
+        let reflected_color = self
+            .radiance_with_depth(
+                &ray.reflect(hit, settings),
+                bvh,
+                lights,
+                settings,
+                depth + 1,
+            )
+            .total();
+
+        let color = match ray.refract(hit, dielectric, settings) {
+            Some((refracted, reflectance)) => {
+                let refracted_color = self
+                    .radiance_with_depth(&refracted, bvh, lights, settings, depth + 1)
+                    .total();
+
+                reflected_color * reflectance + refracted_color * (1.0 - reflectance)
+            }
+            None => reflected_color,
+        };
+
+        // Hitting a back face means the ray travelled through the material
+        if hit.front_face {
+            return color;
+        }
+
+        color * dielectric.transmittance(hit.t)
+    }
+}
@@ -1,11 +1,10 @@
 use na::Vector3;
 
-use crate::{
-    geometry::{Geometry, Intersection, NormalAt},
-    lighting::{Color, Light},
-    materials::Material,
-};
+use crate::{geometry::Hit, materials::Dielectric};
 
+use super::RenderSettings;
+
+#[derive(Debug, Clone)]
 pub struct Ray {
     pub origin: Vector3<f32>,
     direction: Vector3<f32>,
@@ -14,7 +13,7 @@ pub struct Ray {
 impl Ray {
     pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Self {
         Self {
-            origin: origin,
+            origin,
             direction: direction.normalize(),
         }
     }
@@ -27,46 +26,53 @@ impl Ray {
         self.origin + self.direction * t
     }
 
-    pub fn color(&self, entries: &Vec<(&Geometry, &Material)>, lights: &Vec<Light>) -> Color {
-        entries
-            .iter()
-            .filter_map(|(geometry, material)| {
-                geometry
-                    .intersection(self)
-                    .and_then(|t| Some((t, geometry, material)))
-            })
-            // TODO: Does defaulting to less make sense?
-            .min_by(|(ta, ..), (tb, ..)| ta.total_cmp(tb))
-            .map(|(t, geometry, material)| self.color_at(t, geometry, material, lights))
-            .unwrap_or(Color::new_black())
+    pub fn reflect(&self, hit: &Hit, settings: &RenderSettings) -> Ray {
+        let normal = hit.shading_normal;
+        let direction = self.direction() - normal * 2.0 * self.direction().dot(&normal);
+
+        Ray::new(hit.offset_point(settings.bias), direction)
     }
 
-    fn color_at(
+    /// Returns the refracted ray along with the fraction of light reflected
+    /// instead, or None if there's total internal reflection
+    pub fn refract(
         &self,
-        t: f32,
-        geometry: &Geometry,
-        material: &Material,
-        lights: &Vec<Light>,
-    ) -> Color {
-        let hit_point = self.point_at(t);
-        let hit_normal = geometry.normal_at(&hit_point);
+        hit: &Hit,
+        dielectric: &Dielectric,
+        settings: &RenderSettings,
+    ) -> Option<(Ray, f32)> {
+        // Ratio of the refractive index being left to the one being entered
+        let eta = if hit.front_face {
+            1.0 / dielectric.refractive_index
+        } else {
+            dielectric.refractive_index
+        };
 
-        lights
-            .iter()
-            .map(|light| {
-                let light_direction = light.direction_from(&hit_point);
+        let normal = hit.shading_normal;
+        let cos_incident = (-self.direction().dot(&normal)).clamp(0.0, 1.0);
+        let sin_transmitted_squared = eta.powi(2) * (1.0 - cos_incident.powi(2));
+
+        // Beyond the critical angle, there's total internal reflection
+        if sin_transmitted_squared >= 1.0 {
+            return None;
+        }
 
-                // TODO: hit_point used twice. Can we optimize?
-                let diffusion = light_direction.dot(&hit_normal);
-                let specularity = (light_direction - self.direction())
-                    .normalize()
-                    .dot(&hit_normal);
+        let cos_transmitted = (1.0 - sin_transmitted_squared).sqrt();
+        let direction = self.direction() * eta + normal * (eta * cos_incident - cos_transmitted);
 
-                light.color
-                    * (material.ambient_color
-                        + material.diffuse_color * diffusion
-                        + material.specular_color * specularity.powi(material.shininess))
-            })
-            .sum()
+        Some((
+            Ray::new(hit.offset_point(-settings.bias), direction),
+            fresnel(cos_incident, cos_transmitted, eta),
+        ))
     }
 }
+
+/// Fraction of unpolarized light reflected at a boundary between dielectrics,
+/// where eta is the ratio of the incident to the transmitted refractive index
+fn fresnel(cos_incident: f32, cos_transmitted: f32, eta: f32) -> f32 {
+    let perpendicular =
+        (eta * cos_incident - cos_transmitted) / (eta * cos_incident + cos_transmitted);
+    let parallel = (eta * cos_transmitted - cos_incident) / (eta * cos_transmitted + cos_incident);
+
+    (perpendicular.powi(2) + parallel.powi(2)) / 2.0
+}